@@ -8,6 +8,8 @@ pub enum Cli {
     Run(Client),
     /// Start the database server
     Start(Server),
+    /// Reformat a file in place
+    Fmt(Fmt),
 }
 
 #[derive(Parser, Debug, Clone)]
@@ -22,6 +24,20 @@ pub struct Client {
     /// Send expression to a running server
     #[arg(short, long, value_name = "URL")]
     pub server: Option<String>,
+
+    /// Validate tables against the schema declarations in a file
+    #[arg(long, value_name = "PATH", conflicts_with = "server")]
+    pub schema: Option<String>,
+}
+
+#[derive(Parser, Debug, Clone)]
+pub struct Fmt {
+    /// File to format
+    pub path: String,
+
+    /// Exit with an error if the file isn't formatted, rather than writing it
+    #[arg(long)]
+    pub check: bool,
 }
 
 #[derive(Parser, Debug, Clone)]
@@ -0,0 +1,124 @@
+use crate::{
+    eval, parse,
+    parse::{tokenize, Token},
+    Bexp, Env,
+    Exp::Table,
+    Op,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum CompletionKind {
+    Column,
+    Variable,
+    Keyword,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Completion {
+    pub kind: CompletionKind,
+    pub text: String,
+}
+
+const EXP_KEYWORDS: [&str; 8] = [
+    "exists", "false", "nil", "not", "null", "pivot", "rec", "true",
+];
+
+const PREFIXES: [&str; 3] = ["exists", "not", "rec"];
+
+const OPERATORS: [&str; 15] = [
+    ";", "==", "=", "<-", "order", "?", "+", "-", "><", "*", ":", ",", "||", "&&", "as",
+];
+
+// Completes the identifier that ends at `pos`. Columns are offered inside a
+// where condition whose table evaluates in `env`.
+pub fn complete(src: &str, pos: usize, env: &Env) -> Vec<Completion> {
+    let before = src.get(..pos).unwrap_or(src);
+    let prefix_start = before
+        .char_indices()
+        .rev()
+        .find(|&(_, c)| !c.is_alphanumeric() && c != '_')
+        .map_or(0, |(i, c)| i + c.len_utf8());
+    let prefix = &before[prefix_start..];
+    let tokens = tokenize(&before[..prefix_start]);
+
+    let mut completions = vec![];
+    let mut add = |kind, text: &str| {
+        let completion = Completion {
+            kind,
+            text: text.to_string(),
+        };
+        if text.starts_with(prefix) && !completions.contains(&completion) {
+            completions.push(completion);
+        }
+    };
+
+    // Prefix functions are atoms too, but take an operand.
+    let after_exp = match tokens.last() {
+        Some((_, Token::Atom(Bexp::Var(var)))) => !PREFIXES.contains(&var.as_str()),
+        Some((_, Token::Atom(_) | Token::Close)) => true,
+        _ => false,
+    };
+    if after_exp {
+        OPERATORS
+            .iter()
+            .for_each(|keyword| add(CompletionKind::Keyword, keyword));
+    } else {
+        for column in where_columns(&before[..prefix_start], &tokens, env) {
+            add(CompletionKind::Column, &column);
+        }
+        let bound = tokens.windows(2).filter_map(|pair| match pair {
+            [(_, Token::Atom(Bexp::Var(var))), (_, Token::Op(Op::Let))] => Some(var),
+            _ => None,
+        });
+        for var in env.keys().chain(bound) {
+            add(CompletionKind::Variable, var);
+        }
+        EXP_KEYWORDS
+            .iter()
+            .for_each(|keyword| add(CompletionKind::Keyword, keyword));
+    }
+
+    completions.sort();
+    completions
+}
+
+// Finds the `?` whose condition the input ends in, and lists the columns of
+// the table on its left.
+fn where_columns(src: &str, tokens: &[(usize, Token)], env: &Env) -> Vec<String> {
+    let Some(question) = scan_back(tokens, tokens.len(), true, |op| op <= Op::Where) else {
+        return vec![];
+    };
+    if tokens[question].1 != Token::Op(Op::Where) {
+        return vec![];
+    }
+    let start = scan_back(tokens, question, false, |op| op < Op::Where).map_or(0, |i| i + 1);
+    let (start, _) = tokens[start];
+    let (end, _) = tokens[question];
+
+    match parse(&src[start..end]).and_then(|exp| eval(&exp, env)) {
+        Ok((Table(vars, _), _)) => vars,
+        _ => vec![],
+    }
+}
+
+// Walks back from `end` to the nearest operator at the same depth that
+// satisfies `stop`. Unclosed parentheses are stepped out of if `ascend` is
+// set, and are otherwise returned as the boundary.
+fn scan_back(
+    tokens: &[(usize, Token)],
+    end: usize,
+    ascend: bool,
+    stop: impl Fn(Op) -> bool,
+) -> Option<usize> {
+    let mut depth = 0;
+    for (i, (_, token)) in tokens[..end].iter().enumerate().rev() {
+        match token {
+            Token::Close => depth += 1,
+            Token::Open if depth > 0 => depth -= 1,
+            Token::Open if !ascend => return Some(i),
+            Token::Op(op) if depth == 0 && stop(*op) => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
@@ -0,0 +1,129 @@
+use crate::{Exp, Exp::*};
+
+use std::cmp::max;
+
+#[derive(Debug, Clone, Default)]
+pub struct CsvOptions {
+    /// Read `true` and `false` cells as booleans rather than strings.
+    pub infer_bools: bool,
+}
+
+// The first record is the header. Unquoted cells are read as null if empty,
+// as ints if they look like one, and as bools if `infer_bools` is set.
+// Everything else is a string.
+pub fn table_from_csv(text: &str, options: &CsvOptions) -> Result<Exp, String> {
+    let mut records = parse_records(text)?.into_iter();
+    let Some(header) = records.next() else {
+        return Ok(Table(vec![], vec![]));
+    };
+    let vars = header
+        .into_iter()
+        .map(|(field, _)| field)
+        .collect::<Vec<_>>();
+    let mut exps = vec![];
+    for (i, record) in records.enumerate() {
+        if record.len() != vars.len() {
+            return Err(format!(
+                "record {}: expected {} fields, found {}",
+                i + 1,
+                vars.len(),
+                record.len()
+            ));
+        }
+        exps.extend(
+            record
+                .into_iter()
+                .map(|(field, quoted)| infer(field, quoted, options)),
+        );
+    }
+    Ok(Table(vars, exps))
+}
+
+pub fn table_to_csv(exp: &Exp) -> Result<String, String> {
+    let Table(vars, exps) = exp else {
+        return Err("expected table".to_string());
+    };
+    let mut csv = render_record(vars.iter().map(|var| escape(var)));
+    for row in exps.chunks(max(vars.len(), 1)) {
+        let fields = row
+            .iter()
+            .map(|exp| match exp {
+                Bool(bool) => Ok(bool.to_string()),
+                Int(int) => Ok(int.to_string()),
+                Str(str) => Ok(escape(str)),
+                Null => Ok(String::new()),
+                exp => Err(format!("cannot write {} to csv", exp)),
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        csv.push_str(&render_record(fields.into_iter()));
+    }
+    Ok(csv)
+}
+
+fn infer(field: String, quoted: bool, options: &CsvOptions) -> Exp {
+    match field.as_str() {
+        _ if quoted => Str(field),
+        "" => Null,
+        "true" if options.infer_bools => Bool(true),
+        "false" if options.infer_bools => Bool(false),
+        s => s.parse().map(Int).unwrap_or(Str(field)),
+    }
+}
+
+// Fields are paired with whether they were quoted.
+fn parse_records(text: &str) -> Result<Vec<Vec<(String, bool)>>, String> {
+    let mut records = vec![];
+    let mut record = vec![];
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut in_quotes = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if in_quotes && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' if in_quotes => in_quotes = false,
+            '"' if field.is_empty() && !quoted => {
+                quoted = true;
+                in_quotes = true;
+            }
+            c if in_quotes => field.push(c),
+            ',' => record.push((std::mem::take(&mut field), std::mem::take(&mut quoted))),
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\n' => {
+                record.push((std::mem::take(&mut field), std::mem::take(&mut quoted)));
+                records.push(std::mem::take(&mut record));
+            }
+            c => field.push(c),
+        }
+    }
+    if in_quotes {
+        return Err("unterminated quoted field".to_string());
+    }
+    if quoted || !field.is_empty() || !record.is_empty() {
+        record.push((field, quoted));
+        records.push(record);
+    }
+    Ok(records)
+}
+
+// Quotes fields that contain delimiters or would otherwise be read back as
+// something other than a string.
+fn escape(field: &str) -> String {
+    let options = CsvOptions { infer_bools: true };
+    if field.contains([',', '"', '\n', '\r'])
+        || !matches!(infer(field.to_string(), false, &options), Str(_))
+    {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn render_record(fields: impl Iterator<Item = String>) -> String {
+    let mut record = fields.collect::<Vec<_>>().join(",");
+    record.push('\n');
+    record
+}
@@ -0,0 +1,92 @@
+use crate::{eval::check_rows, Exp, Exp::*};
+
+use std::{cmp::max, collections::HashMap};
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct TableDiff {
+    /// Rows of `new` whose key is not in `old`.
+    pub added: Exp,
+    /// Rows of `old` whose key is not in `new`.
+    pub removed: Exp,
+    /// Old and new versions of rows whose key matched but whose other columns
+    /// differ.
+    pub changed: Vec<(Vec<Exp>, Vec<Exp>)>,
+}
+
+// Both tables must have the same columns in the same order, and `key` must
+// identify at most one row in each. Added and changed rows follow the order of
+// `new`, removed rows the order of `old`.
+pub fn table_diff(old: &Exp, new: &Exp, key: &[&str]) -> Result<TableDiff, String> {
+    let (Table(vars, old_exps), Table(new_vars, new_exps)) = (old, new) else {
+        return Err("expected table".to_string());
+    };
+    if vars != new_vars {
+        return Err(format!(
+            "cannot diff columns {} with {}",
+            vars.join(", "),
+            new_vars.join(", ")
+        ));
+    }
+    let key_indices = key
+        .iter()
+        .map(|&k| {
+            vars.iter()
+                .position(|var| var == k)
+                .ok_or_else(|| format!("no column `{}` to diff by", k))
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    check_rows(vars, old_exps)?;
+    check_rows(vars, new_exps)?;
+    let old_rows = keyed(old_exps, vars.len(), &key_indices);
+    let new_rows = keyed(new_exps, vars.len(), &key_indices);
+    let old_index = index(&old_rows)?;
+    let new_index = index(&new_rows)?;
+
+    let mut added = vec![];
+    let mut changed = vec![];
+    for (k, new_row) in &new_rows {
+        match old_index.get(k.as_slice()) {
+            None => added.extend_from_slice(new_row),
+            Some(old_row) if old_row != new_row => {
+                changed.push((old_row.to_vec(), new_row.to_vec()))
+            }
+            Some(_) => {}
+        }
+    }
+    let removed = old_rows
+        .iter()
+        .filter(|(k, _)| !new_index.contains_key(k.as_slice()))
+        .flat_map(|(_, row)| row.iter().cloned())
+        .collect();
+
+    Ok(TableDiff {
+        added: Table(vars.clone(), added),
+        removed: Table(vars.clone(), removed),
+        changed,
+    })
+}
+
+// Keys are compared by their source text, which the serialiser keeps
+// distinct for distinct values, so rows can be looked up in a map.
+type KeyedRow<'a> = (Vec<String>, &'a [Exp]);
+
+// Pairs each row with its key.
+fn keyed<'a>(exps: &'a [Exp], width: usize, key_indices: &[usize]) -> Vec<KeyedRow<'a>> {
+    exps.chunks(max(width, 1))
+        .map(|row| {
+            let key = key_indices.iter().map(|&i| row[i].to_string()).collect();
+            (key, row)
+        })
+        .collect()
+}
+
+// Looks rows up by key. Each key may identify only one row.
+fn index<'a>(rows: &'a [KeyedRow]) -> Result<HashMap<&'a [String], &'a [Exp]>, String> {
+    let mut index = HashMap::new();
+    for (key, row) in rows {
+        if index.insert(key.as_slice(), *row).is_some() {
+            return Err(format!("duplicate key {} in diff", key.join(", ")));
+        }
+    }
+    Ok(index)
+}
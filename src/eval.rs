@@ -1,66 +1,105 @@
-use crate::{Exp, Exp::*};
+use crate::{
+    check, schema::check_row, spill, spill::Spill, Exp, Exp::*, Projection, SchemaEnv, SelectItem,
+    SortKey,
+};
 
-use std::{cmp::max, collections::HashMap};
+use std::{
+    cmp::{max, Ordering},
+    collections::{hash_map::RandomState, HashMap},
+    hash::BuildHasher,
+};
 
 pub type Env = HashMap<String, Exp>;
 
+#[derive(Debug, Clone, Default)]
+pub struct EvalOptions {
+    /// Reject comparisons between different types and non-boolean operands
+    /// to `||` and `&&`, rather than treating them as unequal or false.
+    pub strict: bool,
+    /// Schemas that tables must conform to whenever they are bound.
+    pub schemas: SchemaEnv,
+    /// Seed for `random()` sort keys. Without one, each evaluation shuffles
+    /// differently.
+    pub seed: Option<u64>,
+    /// Most rows an order or product holds in memory besides its result.
+    /// A larger order hands its input over to temporary files in sorted runs
+    /// of this many rows and merges them into the result. A product with a
+    /// larger right side keeps that side in a temporary file and reads it
+    /// back for each row on the left.
+    pub spill_threshold: Option<usize>,
+    /// Most steps a `rec` binding takes towards a fixpoint before failing.
+    /// Defaults to 1000.
+    pub max_iterations: Option<usize>,
+}
+
 pub fn eval(exp: &Exp, env: &Env) -> Result<(Exp, Env), String> {
+    eval_with(exp, env, &EvalOptions::default())
+}
+
+pub fn eval_with(exp: &Exp, env: &Env, options: &EvalOptions) -> Result<(Exp, Env), String> {
     match exp {
-        Let(var, exp, body) => {
-            let (exp, _) = eval(exp, env)?;
-            let mut env = env.clone();
-            env.insert(var.clone(), exp);
-            eval(body, &env)
-        }
-        Select(select_vars, table) => {
-            let (Table(table_vars, exps), _) = eval(table, env)? else {
-                return Err("expected table".to_string());
-            };
-            let var_indices = table_vars
-                .iter()
-                .enumerate()
-                .map(|(i, s)| (s, i))
-                .collect::<HashMap<_, _>>();
-            let keep_indices = select_vars
-                .iter()
-                .filter_map(|k| var_indices.get(k))
-                .cloned()
-                .collect::<Vec<_>>();
-            let exps = exps
-                .chunks(max(table_vars.len(), 1))
-                .flat_map(|row| keep_indices.iter().filter_map(|&i| row.get(i).cloned()))
-                .collect();
-            Ok((Table(select_vars.clone(), exps), env.clone()))
+        Let(var, exp, body) => eval_with(body, &bind(var, exp, env, options)?, options),
+        LetRec(var, exp, body) => eval_with(body, &bind_rec(var, exp, env, options)?, options),
+        Select(items, table) => {
+            let (table, _) = eval_with(table, env, options)?;
+            Ok((select(items, table)?, env.clone()))
         }
-        Where(table, cond) => {
-            let (Table(vars, exps), _) = eval(table, env)? else {
+        Order(table, keys) => {
+            let (Table(vars, exps), _) = eval_with(table, env, options)? else {
                 return Err("expected table".to_string());
             };
-            let exps = exps
-                .chunks(max(vars.len(), 1))
-                .try_fold(vec![], |mut acc, exps| {
-                    let env = vars
+            let key_indices = keys
+                .iter()
+                .map(|key| match key {
+                    SortKey::Column(key) => vars
                         .iter()
-                        .zip(exps)
-                        .map(|(var, exp)| (var.clone(), exp.clone()))
-                        .collect();
-
-                    match eval(cond, &env)? {
-                        (Bool(true), _) => {
-                            acc.extend_from_slice(exps);
-                            Ok(acc)
-                        }
-                        (Bool(false), _) => Ok(acc),
-                        _ => Err("expected boolean in where clause".to_string()),
-                    }
-                })?;
+                        .position(|var| var == key)
+                        .map(Some)
+                        .ok_or_else(|| format!("no column `{}` to order by", key)),
+                    SortKey::Random => Ok(None),
+                })
+                .collect::<Result<Vec<_>, String>>()?;
+            check_rows(&vars, &exps)?;
+            let seed = options
+                .seed
+                .unwrap_or_else(|| RandomState::new().hash_one(0));
+            let compare_rows = |(l_random, l): &(u64, &[Exp]), (r_random, r): &(u64, &[Exp])| {
+                key_indices
+                    .iter()
+                    .map(|key| match key {
+                        Some(i) => compare(&l[*i], &r[*i]),
+                        None => l_random.cmp(r_random),
+                    })
+                    .find(|ordering| ordering.is_ne())
+                    .unwrap_or(Ordering::Equal)
+            };
+            let width = max(vars.len(), 1);
+            let random = |i: usize| splitmix64(seed.wrapping_add(i as u64));
+            let exps = match options.spill_threshold {
+                Some(threshold) if exps.len() / width > threshold => {
+                    spill::sort(exps, width, threshold, random, compare_rows)?
+                }
+                _ => {
+                    let mut rows = exps
+                        .chunks(width)
+                        .enumerate()
+                        .map(|(i, row)| (random(i), row))
+                        .collect::<Vec<_>>();
+                    rows.sort_by(compare_rows);
+                    rows.into_iter().flat_map(|(_, row)| row.to_vec()).collect()
+                }
+            };
             Ok((Table(vars, exps), env.clone()))
         }
+        Where(table, cond) => {
+            let (table, _) = eval_with(table, env, options)?;
+            Ok((filter(table, cond, options, None)?, env.clone()))
+        }
         Union(l, r) => {
-            let (Table(vars, mut exps), _) = eval(l, env)? else {
+            let (Table(vars, mut exps), _) = eval_with(l, env, options)? else {
                 return Err("expected table".to_string());
             };
-            let (Table(r_vars, mut r_exps), _) = eval(r, env)? else {
+            let (Table(r_vars, mut r_exps), _) = eval_with(r, env, options)? else {
                 return Err("expected table".to_string());
             };
             if vars != r_vars {
@@ -70,10 +109,10 @@ pub fn eval(exp: &Exp, env: &Env) -> Result<(Exp, Env), String> {
             Ok((Table(vars, exps), env.clone()))
         }
         Difference(l, r) => {
-            let (Table(l_vars, l_exps), _) = eval(l, env)? else {
+            let (Table(l_vars, l_exps), _) = eval_with(l, env, options)? else {
                 return Err("expected table".to_string());
             };
-            let (Table(r_vars, r_exps), _) = eval(r, env)? else {
+            let (Table(r_vars, r_exps), _) = eval_with(r, env, options)? else {
                 return Err("expected table".to_string());
             };
             if l_vars != r_vars {
@@ -91,56 +130,198 @@ pub fn eval(exp: &Exp, env: &Env) -> Result<(Exp, Env), String> {
                 .collect();
             Ok((Table(vars, exps), env.clone()))
         }
-        Product(l, r) => {
-            let (Table(l_vars, l_exps), _) = eval(l, env)? else {
+        Join(l, r) => {
+            let (Table(l_vars, l_exps), _) = eval_with(l, env, options)? else {
                 return Err("expected table".to_string());
             };
-            let (Table(r_vars, r_exps), _) = eval(r, env)? else {
+            let (Table(r_vars, r_exps), _) = eval_with(r, env, options)? else {
                 return Err("expected table".to_string());
             };
+            let shared = l_vars
+                .iter()
+                .enumerate()
+                .filter_map(|(i, var)| r_vars.iter().position(|v| v == var).map(|j| (i, j)))
+                .collect::<Vec<_>>();
+            let r_keep = (0..r_vars.len())
+                .filter(|j| shared.iter().all(|(_, k)| k != j))
+                .collect::<Vec<_>>();
+            check_rows(&l_vars, &l_exps)?;
+            check_rows(&r_vars, &r_exps)?;
             let exps = l_exps
                 .chunks(max(l_vars.len(), 1))
                 .flat_map(|l_row| {
                     r_exps
                         .chunks(max(r_vars.len(), 1))
-                        .flat_map(move |r_row| [l_row, r_row].concat())
+                        .filter(|r_row| shared.iter().all(|&(i, j)| l_row[i] == r_row[j]))
+                        .flat_map(|r_row| {
+                            l_row
+                                .iter()
+                                .chain(r_keep.iter().map(|&j| &r_row[j]))
+                                .cloned()
+                                .collect::<Vec<_>>()
+                        })
+                        .collect::<Vec<_>>()
                 })
-                .collect::<Vec<_>>();
+                .collect();
+            let vars = l_vars
+                .iter()
+                .chain(r_keep.iter().map(|&j| &r_vars[j]))
+                .cloned()
+                .collect();
+            Ok((Table(vars, exps), env.clone()))
+        }
+        Product(l, r) => {
+            let (Table(l_vars, l_exps), _) = eval_with(l, env, options)? else {
+                return Err("expected table".to_string());
+            };
+            let (Table(r_vars, r_exps), _) = eval_with(r, env, options)? else {
+                return Err("expected table".to_string());
+            };
+            let r_width = max(r_vars.len(), 1);
+            let exps = match options.spill_threshold {
+                // Stream the right side from disk, once per left row.
+                Some(threshold) if r_exps.len() / r_width > threshold => {
+                    let mut spill = Spill::new()?;
+                    for r_row in r_exps.chunks(r_width) {
+                        spill.write_row(r_row)?;
+                    }
+                    drop(r_exps);
+                    let mut exps = vec![];
+                    for l_row in l_exps.chunks(max(l_vars.len(), 1)) {
+                        for r_row in spill.rows()? {
+                            exps.extend_from_slice(l_row);
+                            exps.append(&mut r_row?);
+                        }
+                    }
+                    exps
+                }
+                _ => l_exps
+                    .chunks(max(l_vars.len(), 1))
+                    .flat_map(|l_row| {
+                        r_exps
+                            .chunks(r_width)
+                            .flat_map(move |r_row| [l_row, r_row].concat())
+                    })
+                    .collect::<Vec<_>>(),
+            };
             let vars = [l_vars, r_vars].concat();
             Ok((Table(vars, exps), env.clone()))
         }
+        Pivot(table, on, value) => {
+            let (Table(vars, exps), _) = eval_with(table, env, options)? else {
+                return Err("expected table".to_string());
+            };
+            let index = |var: &String| {
+                vars.iter()
+                    .position(|v| v == var)
+                    .ok_or_else(|| format!("no column `{}` to pivot", var))
+            };
+            let on_index = index(on)?;
+            let value_index = index(value)?;
+            let group_indices = (0..vars.len())
+                .filter(|&i| i != on_index && i != value_index)
+                .collect::<Vec<_>>();
+            check_rows(&vars, &exps)?;
+
+            let mut columns: Vec<Exp> = vec![];
+            let mut groups: Vec<(Vec<Exp>, HashMap<usize, Exp>)> = vec![];
+            for row in exps.chunks(max(vars.len(), 1)) {
+                let column = match columns.iter().position(|c| *c == row[on_index]) {
+                    Some(column) => column,
+                    None => {
+                        columns.push(row[on_index].clone());
+                        columns.len() - 1
+                    }
+                };
+                let key = group_indices
+                    .iter()
+                    .map(|&i| row[i].clone())
+                    .collect::<Vec<_>>();
+                let group = match groups.iter().position(|(k, _)| *k == key) {
+                    Some(group) => group,
+                    None => {
+                        groups.push((key, HashMap::new()));
+                        groups.len() - 1
+                    }
+                };
+                if groups[group]
+                    .1
+                    .insert(column, row[value_index].clone())
+                    .is_some()
+                {
+                    return Err(format!("duplicate value for {} in pivot", row[on_index]));
+                }
+            }
+
+            let pivot_vars = group_indices
+                .iter()
+                .map(|&i| vars[i].clone())
+                .chain(columns.iter().map(|column| match column {
+                    Str(str) => str.clone(),
+                    exp => exp.to_string(),
+                }))
+                .collect::<Vec<_>>();
+            // Values like 'true' and true, or a value named after a group column,
+            // would give the result two columns with the same name.
+            for (i, var) in pivot_vars.iter().enumerate().skip(group_indices.len()) {
+                if pivot_vars[..i].contains(var) {
+                    return Err(format!("duplicate column `{}` in pivot", var));
+                }
+            }
+            let exps = groups
+                .into_iter()
+                .flat_map(|(key, mut cells)| {
+                    let cells = (0..columns.len())
+                        .map(|column| cells.remove(&column).unwrap_or(Null))
+                        .collect::<Vec<_>>();
+                    [key, cells].concat()
+                })
+                .collect();
+            Ok((Table(pivot_vars, exps), env.clone()))
+        }
         Table(l, r) => {
             let exps = r
                 .iter()
-                .map(|exp| eval(exp, env).map(|(exp, _)| exp))
+                .map(|exp| eval_with(exp, env, options).map(|(exp, _)| exp))
                 .collect::<Result<Vec<Exp>, String>>()?;
             Ok((Table(l.clone(), exps), env.clone()))
         }
         Or(l, r) => {
-            if let (Bool(true), _) = eval(l, env)? {
+            if eval_bool(l, env, options, false)? {
                 return Ok((Bool(true), env.clone()));
             }
-            if let (Bool(true), _) = eval(r, env)? {
+            if eval_bool(r, env, options, false)? {
                 return Ok((Bool(true), env.clone()));
             }
             Ok((Bool(false), env.clone()))
         }
         Equals(l, r) => {
-            let (l, _) = eval(l, env)?;
-            let (r, _) = eval(r, env)?;
+            let (l, _) = eval_with(l, env, options)?;
+            let (r, _) = eval_with(r, env, options)?;
+            if options.strict && l != Null && r != Null && type_name(&l) != type_name(&r) {
+                return Err(format!(
+                    "cannot compare {} with {} in strict mode",
+                    type_name(&l),
+                    type_name(&r)
+                ));
+            }
             Ok((Bool(l == r), env.clone()))
         }
         And(l, r) => {
-            if let (Bool(false), _) = eval(l, env)? {
+            if !eval_bool(l, env, options, true)? {
                 return Ok((Bool(false), env.clone()));
             }
-            if let (Bool(false), _) = eval(r, env)? {
+            if !eval_bool(r, env, options, true)? {
                 return Ok((Bool(false), env.clone()));
             }
             Ok((Bool(true), env.clone()))
         }
+        Exists(table) => match eval_limit(table, env, options, 1)? {
+            Table(_, exps) => Ok((Bool(!exps.is_empty()), env.clone())),
+            _ => Err("expected table".to_string()),
+        },
         Not(exp) => {
-            let exp = eval(exp, env)?;
+            let exp = eval_with(exp, env, options)?;
             match exp {
                 (Bool(bool), _) => Ok((Bool(!bool), env.clone())),
                 _ => Err(format!("Expected boolean, found {:?}", exp)),
@@ -153,3 +334,257 @@ pub fn eval(exp: &Exp, env: &Env) -> Result<(Exp, Env), String> {
         exp => Ok((exp.clone(), env.clone())),
     }
 }
+
+fn bind(var: &str, exp: &Exp, env: &Env, options: &EvalOptions) -> Result<Env, String> {
+    bind_row(var, exp, env, options).map_err(|(_, e)| e)
+}
+
+// Like `bind`, but a schema error also gives the index of the failing row.
+fn bind_row(
+    var: &str,
+    exp: &Exp,
+    env: &Env,
+    options: &EvalOptions,
+) -> Result<Env, (Option<usize>, String)> {
+    let (exp, _) = eval_with(exp, env, options).map_err(|e| (None, e))?;
+    if let Some(schema) = options.schemas.get(var) {
+        check_row(schema, &exp).map_err(|(row, e)| (row, format!("{}: {}", var, e)))?;
+    }
+    let mut env = env.clone();
+    env.insert(var.to_string(), exp);
+    Ok(env)
+}
+
+// An error in a program's top-level bindings: the index of the binding it
+// came from and, if the binding is a table literal that failed its schema,
+// the index of the row.
+pub(crate) struct StatementError {
+    pub(crate) statement: usize,
+    pub(crate) row: Option<usize>,
+    pub(crate) error: String,
+}
+
+// Evaluates a chain of bindings like `eval_with`, keeping track of which
+// binding is being evaluated.
+pub(crate) fn eval_statements(
+    exp: &Exp,
+    env: &Env,
+    options: &EvalOptions,
+) -> Result<(Exp, Env), StatementError> {
+    let mut exp = exp;
+    let mut env = env.clone();
+    let mut statement = 0;
+    loop {
+        let located = |row, error| StatementError {
+            statement,
+            row,
+            error,
+        };
+        match exp {
+            Let(var, value, body) => {
+                env = bind_row(var, value, &env, options).map_err(|(row, e)| {
+                    let row = row.filter(|_| matches!(**value, Table(_, _)));
+                    located(row, e)
+                })?;
+                exp = body;
+            }
+            LetRec(var, value, body) => {
+                env = bind_rec(var, value, &env, options).map_err(|e| located(None, e))?;
+                exp = body;
+            }
+            exp => return eval_with(exp, &env, options).map_err(|e| located(None, e)),
+        }
+        statement += 1;
+    }
+}
+
+// Evaluates `rec var = base + step` by starting `var` at `base` and
+// re-evaluating the union until it stops gaining rows. Each step must keep
+// every row of the last one.
+fn bind_rec(var: &str, exp: &Exp, env: &Env, options: &EvalOptions) -> Result<Env, String> {
+    let Union(base, _) = exp else {
+        return Err(format!("expected rec {} = base + step", var));
+    };
+    let (vars, mut rows) = distinct(eval_with(base, env, options)?.0)?;
+    let mut env = env.clone();
+    for _ in 0..options.max_iterations.unwrap_or(1000) {
+        env.insert(var.to_string(), Table(vars.clone(), rows.concat()));
+        let (_, next) = distinct(eval_with(exp, &env, options)?.0)?;
+        if rows.iter().any(|row| !next.contains(row)) {
+            return Err(format!("recursion for {} is not monotone", var));
+        }
+        if next.len() == rows.len() {
+            if let Some(schema) = options.schemas.get(var) {
+                check(schema, &env[var]).map_err(|e| format!("{}: {}", var, e))?;
+            }
+            return Ok(env);
+        }
+        rows = next;
+    }
+    Err(format!("{} did not reach a fixpoint", var))
+}
+
+// Operators that index into rows need the last row to be complete, which a
+// table literal doesn't guarantee.
+pub(crate) fn check_rows(vars: &[String], exps: &[Exp]) -> Result<(), String> {
+    let width = max(vars.len(), 1);
+    match exps.len() % width {
+        0 => Ok(()),
+        len => Err(format!(
+            "row {} has {} of {} values",
+            exps.len() / width + 1,
+            len,
+            width
+        )),
+    }
+}
+
+fn distinct(exp: Exp) -> Result<(Vec<String>, Vec<Vec<Exp>>), String> {
+    let Table(vars, exps) = exp else {
+        return Err("expected table".to_string());
+    };
+    let mut rows: Vec<Vec<Exp>> = vec![];
+    for row in exps.chunks(max(vars.len(), 1)) {
+        if !rows.iter().any(|r| r == row) {
+            rows.push(row.to_vec());
+        }
+    }
+    Ok((vars, rows))
+}
+
+// Evaluates a table only as far as needed to find `limit` rows, which lets
+// `exists` stop scanning at the first match.
+fn eval_limit(exp: &Exp, env: &Env, options: &EvalOptions, limit: usize) -> Result<Exp, String> {
+    match exp {
+        Let(var, exp, body) => eval_limit(body, &bind(var, exp, env, options)?, options, limit),
+        LetRec(var, exp, body) => {
+            eval_limit(body, &bind_rec(var, exp, env, options)?, options, limit)
+        }
+        Select(items, table) => select(items, eval_limit(table, env, options, limit)?),
+        Where(table, cond) => {
+            let (table, _) = eval_with(table, env, options)?;
+            filter(table, cond, options, Some(limit))
+        }
+        exp => match eval_with(exp, env, options)? {
+            (Table(vars, mut exps), _) => {
+                exps.truncate(limit * max(vars.len(), 1));
+                Ok(Table(vars, exps))
+            }
+            (exp, _) => Ok(exp),
+        },
+    }
+}
+
+fn select(items: &[SelectItem], table: Exp) -> Result<Exp, String> {
+    let Table(table_vars, exps) = table else {
+        return Err("expected table".to_string());
+    };
+    let var_indices = table_vars
+        .iter()
+        .enumerate()
+        .map(|(i, s)| (s, i))
+        .collect::<HashMap<_, _>>();
+    // `None` stands for the row number.
+    let keep_indices = items
+        .iter()
+        .filter_map(|item| match &item.source {
+            Projection::Column(column) => var_indices.get(column).map(|&i| Some(i)),
+            Projection::RowNumber => Some(None),
+        })
+        .collect::<Vec<_>>();
+    let exps = exps
+        .chunks(max(table_vars.len(), 1))
+        .enumerate()
+        .flat_map(|(n, row)| {
+            keep_indices.iter().filter_map(move |i| match i {
+                Some(i) => row.get(*i).cloned(),
+                None => Some(Int(n as i64 + 1)),
+            })
+        })
+        .collect();
+    let vars = items.iter().map(|item| item.name().to_string()).collect();
+    Ok(Table(vars, exps))
+}
+
+fn filter(
+    table: Exp,
+    cond: &Exp,
+    options: &EvalOptions,
+    limit: Option<usize>,
+) -> Result<Exp, String> {
+    let Table(vars, exps) = table else {
+        return Err("expected table".to_string());
+    };
+    let mut rows = 0;
+    let mut result = vec![];
+    for row in exps.chunks(max(vars.len(), 1)) {
+        if limit.is_some_and(|limit| rows >= limit) {
+            break;
+        }
+        let env = vars
+            .iter()
+            .zip(row)
+            .map(|(var, exp)| (var.clone(), exp.clone()))
+            .collect();
+
+        match eval_with(cond, &env, options)? {
+            (Bool(true), _) => {
+                rows += 1;
+                result.extend_from_slice(row);
+            }
+            (Bool(false), _) => {}
+            _ => return Err("expected boolean in where clause".to_string()),
+        }
+    }
+    Ok(Table(vars, result))
+}
+
+// Outside strict mode a non-boolean operand takes the value that doesn't
+// short-circuit: `||` carries on past it and `&&` passes it through.
+fn eval_bool(exp: &Exp, env: &Env, options: &EvalOptions, default: bool) -> Result<bool, String> {
+    match eval_with(exp, env, options)? {
+        (Bool(bool), _) => Ok(bool),
+        (exp, _) if options.strict => Err(format!(
+            "expected boolean in strict mode, found {}",
+            type_name(&exp)
+        )),
+        _ => Ok(default),
+    }
+}
+
+// Values of different types are ordered null, bool, int, str, and then
+// anything else, which compares equal.
+fn compare(l: &Exp, r: &Exp) -> Ordering {
+    let rank = |exp: &Exp| match exp {
+        Null => 0,
+        Bool(_) => 1,
+        Int(_) => 2,
+        Str(_) => 3,
+        _ => 4,
+    };
+    match (l, r) {
+        (Bool(l), Bool(r)) => l.cmp(r),
+        (Int(l), Int(r)) => l.cmp(r),
+        (Str(l), Str(r)) => l.cmp(r),
+        (l, r) => rank(l).cmp(&rank(r)),
+    }
+}
+
+// A small, well-mixed hash from sequential seeds to random sort keys.
+fn splitmix64(seed: u64) -> u64 {
+    let mut z = seed.wrapping_add(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+fn type_name(exp: &Exp) -> &'static str {
+    match exp {
+        Bool(_) => "bool",
+        Int(_) => "int",
+        Str(_) => "str",
+        Table(_, _) => "table",
+        Null => "null",
+        _ => "expression",
+    }
+}
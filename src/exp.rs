@@ -1,18 +1,71 @@
 #[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Exp {
     Let(String, Box<Exp>, Box<Exp>),
-    Select(Vec<String>, Box<Exp>),
+    LetRec(String, Box<Exp>, Box<Exp>),
+    Select(Vec<SelectItem>, Box<Exp>),
+    Order(Box<Exp>, Vec<SortKey>),
     Where(Box<Exp>, Box<Exp>),
     Union(Box<Exp>, Box<Exp>),
     Difference(Box<Exp>, Box<Exp>),
+    Join(Box<Exp>, Box<Exp>),
     Product(Box<Exp>, Box<Exp>),
+    Pivot(Box<Exp>, String, String),
     Table(Vec<String>, Vec<Exp>),
     Or(Box<Exp>, Box<Exp>),
     Equals(Box<Exp>, Box<Exp>),
     And(Box<Exp>, Box<Exp>),
+    Exists(Box<Exp>),
     Not(Box<Exp>),
     Bool(bool),
     Int(i64),
     Str(String),
+    Null,
     Var(String),
 }
+
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SelectItem {
+    pub source: Projection,
+    pub alias: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Projection {
+    Column(String),
+    /// The 1-based position of each row in the table being selected from.
+    RowNumber,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SortKey {
+    Column(String),
+    Random,
+}
+
+impl SelectItem {
+    pub fn new(source: &str) -> Self {
+        SelectItem {
+            source: Projection::Column(source.to_string()),
+            alias: None,
+        }
+    }
+
+    pub fn aliased(source: &str, alias: &str) -> Self {
+        SelectItem {
+            source: Projection::Column(source.to_string()),
+            alias: Some(alias.to_string()),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match (&self.alias, &self.source) {
+            (Some(alias), _) => alias,
+            (None, Projection::Column(column)) => column,
+            (None, Projection::RowNumber) => "row_number",
+        }
+    }
+}
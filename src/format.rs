@@ -0,0 +1,272 @@
+use crate::{
+    parse,
+    parse::{is_junk, tokenize, Op, Token},
+    serialise,
+    serialise::serialise_table_rows,
+    Exp,
+    Exp::*,
+};
+
+#[derive(Debug)]
+enum Item {
+    // One or more blank lines.
+    Blank,
+    Comment(String),
+    // A statement, whether it ends in `;`, a comment on the same line, and
+    // comments at the ends of lines inside it with their offsets.
+    Code(String, bool, Option<String>, Vec<(usize, String)>),
+}
+
+// Reformats a program one top-level statement at a time. Comments between
+// statements keep their place, as do comments at the end of a statement's
+// line, and runs of blank lines become a single blank line. Inside a
+// statement, comments at the end of a line of a table literal stay on its
+// header or row. Other comments inside a statement can't be placed and are
+// an error.
+pub fn format(text: &str) -> Result<String, String> {
+    parse(text)?;
+
+    let mut out = String::new();
+    for item in split(text)? {
+        match item {
+            Item::Blank => out.push('\n'),
+            Item::Comment(comment) => {
+                out.push_str(&comment);
+                out.push('\n');
+            }
+            Item::Code(code, terminated, comment, inner) => {
+                let (formatted, last) = if terminated {
+                    let (prefix, exp) = match parse(&format!("{}; nil", code))? {
+                        Let(var, exp, _) => (serialise(Var(var)), exp),
+                        LetRec(var, exp, _) => (format!("rec {}", serialise(Var(var))), exp),
+                        exp => return Err(format!("expected let, got {}", exp)),
+                    };
+                    let lines = table_lines(&code, &inner, &exp)?;
+                    let (exp, last) = format_exp(*exp, " = ", "\n  ", "  ", lines);
+                    (format!("{}{};", prefix, exp), last)
+                } else {
+                    if !inner.is_empty() {
+                        return Err(INNER_COMMENT.to_string());
+                    }
+                    format_exp(parse(&code)?, "", "", "", vec![])
+                };
+                out.push_str(&formatted);
+                if let Some(comment) = [last, comment].into_iter().flatten().reduce(join) {
+                    out.push(' ');
+                    out.push_str(&comment);
+                }
+                out.push('\n');
+            }
+        }
+    }
+    Ok(out)
+}
+
+const INNER_COMMENT: &str = "cannot format a comment inside an expression";
+
+// Tables with rows are written with the header and each row on a line of
+// their own. `comments` go at the end of those lines, the header being line
+// 0. The comment for the last line is returned so it can follow the `;`.
+fn format_exp(
+    exp: Exp,
+    sep: &str,
+    table_sep: &str,
+    indent: &str,
+    mut comments: Vec<Option<String>>,
+) -> (String, Option<String>) {
+    match exp {
+        Table(vars, exps) if !vars.is_empty() && !exps.is_empty() => {
+            let (header, rows) = serialise_table_rows(vars, exps);
+            comments.resize(rows.len() + 1, None);
+            let last = comments.pop().flatten();
+            let mut lines = vec![format!("{}{}{} :", sep.trim_end(), table_sep, header)];
+            lines.extend(rows.iter().enumerate().map(|(i, row)| {
+                let comma = if i + 1 < rows.len() { "," } else { "" };
+                format!("{}{}{}", indent, row, comma)
+            }));
+            let text = lines
+                .into_iter()
+                .zip(comments.into_iter().chain([None]))
+                .map(|(line, comment)| match comment {
+                    Some(comment) => format!("{} {}", line, comment),
+                    None => line,
+                })
+                .collect::<Vec<_>>()
+                .join("\n");
+            (text, last)
+        }
+        exp @ (Let(..) | LetRec(..)) => (format!("{}({})", sep, serialise(exp)), None),
+        exp => (format!("{}{}", sep, serialise(exp)), None),
+    }
+}
+
+// Finds the line of the table literal bound by `code` that each comment ends:
+// the header, or the row of the cell before it.
+fn table_lines(
+    code: &str,
+    comments: &[(usize, String)],
+    exp: &Exp,
+) -> Result<Vec<Option<String>>, String> {
+    let mut lines: Vec<Option<String>> = vec![];
+    for (offset, comment) in comments {
+        let Table(vars, exps) = exp else {
+            return Err(INNER_COMMENT.to_string());
+        };
+        if vars.is_empty() || exps.is_empty() {
+            return Err(INNER_COMMENT.to_string());
+        }
+        let mut depth = 0;
+        let mut colon = false;
+        let mut cells = 0;
+        let mut after_comma = false;
+        for (_, token) in tokenize(&code[..*offset]) {
+            after_comma = false;
+            match token {
+                Token::Open => depth += 1,
+                Token::Close => depth -= 1,
+                Token::Op(Op::Table) if depth == 0 => colon = true,
+                Token::Op(Op::Item) if depth == 0 && colon => {
+                    cells += 1;
+                    after_comma = true;
+                }
+                Token::Atom(_) if depth == 0 && colon && cells == 0 => cells = 1,
+                _ => {}
+            }
+        }
+        if depth != 0 || !colon {
+            return Err(INNER_COMMENT.to_string());
+        }
+        // A comment after a row's trailing comma still belongs to that row.
+        let cell = if after_comma { cells - 1 } else { cells };
+        let line = match cell {
+            0 => 0,
+            cell => (cell - 1) / vars.len() + 1,
+        };
+        if lines.len() <= line {
+            lines.resize(line + 1, None);
+        }
+        lines[line] = Some(match lines[line].take() {
+            Some(previous) => join(previous, comment.clone()),
+            None => comment.clone(),
+        });
+    }
+    Ok(lines)
+}
+
+fn join(l: String, r: String) -> String {
+    format!("{} {}", l, r)
+}
+
+fn split(text: &str) -> Result<Vec<Item>, String> {
+    let mut items = vec![];
+    let mut code = String::new();
+    let mut inner = vec![];
+    let mut depth = 0;
+    let mut newlines = 0;
+    // Whether we're still on the line of the previous statement's `;`.
+    let mut after_code = false;
+    let mut chars = text.char_indices().peekable();
+
+    while let Some((i, c)) = chars.next() {
+        let rest = &text[i..];
+        let comment_len = if rest.starts_with("--") {
+            Some(rest.find('\n').unwrap_or(rest.len()))
+        } else if rest.starts_with("/*") {
+            Some(rest.find("*/").map_or(rest.len(), |end| end + 2))
+        } else {
+            None
+        };
+
+        if let Some(len) = comment_len {
+            let comment = rest[..len].trim_end().to_string();
+            let after = &rest[len..];
+            if !code.trim().is_empty() && (depth != 0 || !is_junk(after)) {
+                if !after[..after.find('\n').unwrap_or(after.len())]
+                    .trim()
+                    .is_empty()
+                {
+                    return Err(INNER_COMMENT.to_string());
+                }
+                inner.push((code.len(), comment));
+                code.push(' ');
+                while chars.peek().is_some_and(|&(j, _)| j < i + len) {
+                    chars.next();
+                }
+                continue;
+            }
+            if !code.trim().is_empty() {
+                push_code(&mut items, &mut code, &mut inner, false, newlines);
+                after_code = true;
+            }
+            match items.last_mut() {
+                Some(Item::Code(_, _, trailing @ None, _)) if after_code => {
+                    *trailing = Some(comment)
+                }
+                _ => {
+                    if newlines > 1 && !items.is_empty() {
+                        items.push(Item::Blank);
+                    }
+                    items.push(Item::Comment(comment));
+                }
+            }
+            newlines = 0;
+            after_code = false;
+            while chars.peek().is_some_and(|&(j, _)| j < i + len) {
+                chars.next();
+            }
+            continue;
+        }
+
+        match c {
+            '\n' if code.trim().is_empty() => {
+                newlines += 1;
+                after_code = false;
+            }
+            c if c.is_whitespace() && code.trim().is_empty() => {}
+            ';' if depth == 0 => {
+                push_code(&mut items, &mut code, &mut inner, true, newlines);
+                newlines = 0;
+                after_code = true;
+            }
+            '\'' | '`' => {
+                code.push(c);
+                for (_, next) in chars.by_ref() {
+                    code.push(next);
+                    if next == c {
+                        break;
+                    }
+                }
+            }
+            c => {
+                match c {
+                    '(' => depth += 1,
+                    ')' => depth -= 1,
+                    _ => {}
+                }
+                code.push(c);
+            }
+        }
+    }
+    if !code.trim().is_empty() {
+        push_code(&mut items, &mut code, &mut inner, false, newlines);
+    }
+    Ok(items)
+}
+
+fn push_code(
+    items: &mut Vec<Item>,
+    code: &mut String,
+    inner: &mut Vec<(usize, String)>,
+    terminated: bool,
+    newlines: usize,
+) {
+    if newlines > 1 && !items.is_empty() {
+        items.push(Item::Blank);
+    }
+    items.push(Item::Code(
+        std::mem::take(code),
+        terminated,
+        None,
+        std::mem::take(inner),
+    ));
+}
@@ -1,19 +1,45 @@
 mod cli;
 mod client;
+mod complete;
+mod csv;
+mod diff;
 mod eval;
 mod exp;
+mod format;
+mod minimize;
 mod parse;
+mod prepare;
+mod schema;
 mod serialise;
 mod server;
+mod spill;
 
-pub use cli::{Cli, Client, Server};
+pub use cli::{Cli, Client, Fmt, Server};
 pub use client::client;
-pub use eval::{eval, Env};
-pub use exp::Exp;
-pub use parse::{parse, Bexp, Op, Side};
+pub use complete::{complete, Completion, CompletionKind};
+pub use csv::{table_from_csv, table_to_csv, CsvOptions};
+pub use diff::{table_diff, TableDiff};
+pub use eval::{eval, eval_with, Env, EvalOptions};
+pub use exp::{Exp, Projection, SelectItem, SortKey};
+pub use format::format;
+pub use minimize::{minimize, minimize_traced, Rule};
+pub use parse::{parse, parse_with, Bexp, Op, ParseOptions, Side};
+pub use prepare::{prepare, PreparedQuery};
+pub use schema::{check, parse_schemas, Schema, SchemaEnv, Type};
 pub use serialise::serialise;
 pub use server::server;
 
 pub fn read_eval(text: &str, env: &Env) -> Result<(Exp, Env), String> {
     eval(&parse(text)?, env)
 }
+
+/// Like `read_eval`, but errors from a top-level binding start with the line
+/// of the statement. A table literal that fails its schema is reported at the
+/// line of the failing row.
+pub fn read_eval_lines(text: &str, env: &Env, options: &EvalOptions) -> Result<(Exp, Env), String> {
+    eval::eval_statements(&parse(text)?, env, options).map_err(|e| {
+        let offset = parse::statement_offset(text, e.statement, e.row);
+        let line = text[..offset].matches('\n').count() + 1;
+        format!("line {}: {}", line, e.error)
+    })
+}
@@ -1,33 +1,81 @@
-use sdb::{client, read_eval, serialise, server, Cli, Env};
+use sdb::{
+    client, eval_with, format, parse, parse_schemas, read_eval_lines, serialise, server, Cli, Env,
+    EvalOptions,
+};
 
 use clap::Parser;
-use std::fs;
+use std::{fs, process};
 
 fn main() {
     let cli = Cli::parse();
 
     match cli {
         Cli::Run(conf) => {
-            let text = if conf.expression {
-                conf.target
+            let (text, source) = if conf.expression {
+                (conf.target, None)
             } else {
-                match fs::read_to_string(conf.target) {
-                    Ok(text) => text,
+                match fs::read_to_string(&conf.target) {
+                    Ok(text) => (text, Some(conf.target)),
                     Err(e) => return eprintln!("Error reading file: {}", e),
                 }
             };
 
+            let mut options = EvalOptions::default();
+            if let Some(path) = conf.schema {
+                let text = match fs::read_to_string(&path) {
+                    Ok(text) => text,
+                    Err(e) => return eprintln!("Error reading schema: {}", e),
+                };
+                options.schemas = match parse_schemas(&text) {
+                    Ok(schemas) => schemas,
+                    Err(e) => return eprintln!("Error loading schema: {}: {}", path, e),
+                };
+            }
+
             match conf.server {
                 Some(url) => match client(&text, &url) {
                     Ok(result) => println!("{}", result),
                     Err(e) => eprintln!("Error running client: {}", e),
                 },
-                None => match read_eval(&text, &Env::new()) {
-                    Ok((result, _)) => println!("{}", serialise(result)),
-                    Err(e) => eprintln!("Error evaluating program: {}", e),
+                None => match source {
+                    Some(path) => match read_eval_lines(&text, &Env::new(), &options) {
+                        Ok((result, _)) => println!("{}", serialise(result)),
+                        Err(e) => eprintln!("Error evaluating program: {}: {}", path, e),
+                    },
+                    None => {
+                        match parse(&text).and_then(|exp| eval_with(&exp, &Env::new(), &options)) {
+                            Ok((result, _)) => println!("{}", serialise(result)),
+                            Err(e) => eprintln!("Error evaluating program: {}", e),
+                        }
+                    }
                 },
             }
         }
+        Cli::Fmt(conf) => {
+            let text = match fs::read_to_string(&conf.path) {
+                Ok(text) => text,
+                Err(e) => {
+                    eprintln!("Error reading file: {}", e);
+                    process::exit(1);
+                }
+            };
+            let formatted = match format(&text) {
+                Ok(formatted) => formatted,
+                Err(e) => {
+                    eprintln!("Error formatting program: {}: {}", conf.path, e);
+                    process::exit(1);
+                }
+            };
+            if conf.check {
+                if formatted != text {
+                    eprintln!("{} is not formatted", conf.path);
+                    process::exit(1);
+                }
+            } else if let Err(e) = fs::write(&conf.path, formatted) {
+                eprintln!("Error writing file: {}", e);
+                process::exit(1);
+            }
+        }
         Cli::Start(conf) => {
             println!("Starting server");
             println!("Directory: {}", conf.directory);
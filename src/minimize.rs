@@ -0,0 +1,147 @@
+use crate::{Exp, Exp::*};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Rule {
+    FoldNot,
+    FoldOr,
+    FoldAnd,
+    FoldEquals,
+    FoldWhere,
+    DeadLet,
+    Reassociate,
+}
+
+// Rewrites assume the query is well-typed: a query that fails may minimize to
+// one that succeeds, but a query that succeeds always keeps its result.
+// Bindings that the body never reads are dropped, so a minimized query no
+// longer writes them when it runs on the server.
+pub fn minimize(exp: Exp) -> Exp {
+    minimize_with(exp, &mut None)
+}
+
+// Also returns each rewrite in the order it was applied, along with the
+// subexpression before and after.
+pub fn minimize_traced(exp: Exp) -> (Exp, Vec<(Rule, Exp, Exp)>) {
+    let mut trace = Some(vec![]);
+    let exp = minimize_with(exp, &mut trace);
+    (exp, trace.unwrap_or_default())
+}
+
+fn minimize_with(exp: Exp, trace: &mut Option<Vec<(Rule, Exp, Exp)>>) -> Exp {
+    let exp = map_children(exp, &mut |exp| minimize_with(exp, trace));
+    let before = trace.as_ref().map(|_| exp.clone());
+    match rewrite(exp) {
+        Ok((rule, exp)) => {
+            if let (Some(trace), Some(before)) = (trace.as_mut(), before) {
+                trace.push((rule, before, exp.clone()));
+            }
+            minimize_with(exp, trace)
+        }
+        Err(exp) => exp,
+    }
+}
+
+fn rewrite(exp: Exp) -> Result<(Rule, Exp), Exp> {
+    match exp {
+        Not(exp) => match *exp {
+            Bool(bool) => Ok((Rule::FoldNot, Bool(!bool))),
+            exp => Err(Not(Box::new(exp))),
+        },
+        Or(l, r) => match (*l, *r) {
+            (Bool(true), _) => Ok((Rule::FoldOr, Bool(true))),
+            (Bool(false), r) if is_bool(&r) => Ok((Rule::FoldOr, r)),
+            (l, Bool(false)) if is_bool(&l) => Ok((Rule::FoldOr, l)),
+            (l, Or(rl, rr)) => Ok((Rule::Reassociate, Or(Box::new(Or(Box::new(l), rl)), rr))),
+            (l, r) => Err(Or(Box::new(l), Box::new(r))),
+        },
+        And(l, r) => match (*l, *r) {
+            (Bool(false), _) => Ok((Rule::FoldAnd, Bool(false))),
+            (Bool(true), r) if is_bool(&r) => Ok((Rule::FoldAnd, r)),
+            (l, Bool(true)) if is_bool(&l) => Ok((Rule::FoldAnd, l)),
+            (l, And(rl, rr)) => Ok((Rule::Reassociate, And(Box::new(And(Box::new(l), rl)), rr))),
+            (l, r) => Err(And(Box::new(l), Box::new(r))),
+        },
+        Equals(l, r) => match (*l, *r) {
+            (l, r) if is_literal(&l) && is_literal(&r) => Ok((Rule::FoldEquals, Bool(l == r))),
+            (l, r) => Err(Equals(Box::new(l), Box::new(r))),
+        },
+        Where(table, cond) => match *cond {
+            Bool(true) => Ok((Rule::FoldWhere, *table)),
+            cond => Err(Where(table, Box::new(cond))),
+        },
+        Let(var, _, body) if !occurs(&var, &body) => Ok((Rule::DeadLet, *body)),
+        Union(l, r) => match *r {
+            Union(rl, rr) => Ok((Rule::Reassociate, Union(Box::new(Union(l, rl)), rr))),
+            r => Err(Union(l, Box::new(r))),
+        },
+        Product(l, r) => match *r {
+            Product(rl, rr) => Ok((Rule::Reassociate, Product(Box::new(Product(l, rl)), rr))),
+            r => Err(Product(l, Box::new(r))),
+        },
+        exp => Err(exp),
+    }
+}
+
+fn map_children(exp: Exp, f: &mut impl FnMut(Exp) -> Exp) -> Exp {
+    let mut f_box = |exp: Box<Exp>| Box::new(f(*exp));
+    match exp {
+        Let(var, exp, body) => Let(var, f_box(exp), f_box(body)),
+        // The top union splits the base from the step, so it can't be
+        // reassociated.
+        LetRec(var, exp, body) => match *exp {
+            Union(base, step) => {
+                LetRec(var, Box::new(Union(f_box(base), f_box(step))), f_box(body))
+            }
+            exp => LetRec(var, f_box(Box::new(exp)), f_box(body)),
+        },
+        Select(items, table) => Select(items, f_box(table)),
+        Order(table, vars) => Order(f_box(table), vars),
+        Where(table, cond) => Where(f_box(table), f_box(cond)),
+        Union(l, r) => Union(f_box(l), f_box(r)),
+        Difference(l, r) => Difference(f_box(l), f_box(r)),
+        Join(l, r) => Join(f_box(l), f_box(r)),
+        Product(l, r) => Product(f_box(l), f_box(r)),
+        Pivot(table, on, value) => Pivot(f_box(table), on, value),
+        Table(vars, exps) => Table(vars, exps.into_iter().map(f).collect()),
+        Or(l, r) => Or(f_box(l), f_box(r)),
+        Equals(l, r) => Equals(f_box(l), f_box(r)),
+        And(l, r) => And(f_box(l), f_box(r)),
+        Exists(exp) => Exists(f_box(exp)),
+        Not(exp) => Not(f_box(exp)),
+        exp => exp,
+    }
+}
+
+fn occurs(var: &str, exp: &Exp) -> bool {
+    match exp {
+        Let(v, exp, body) => occurs(var, exp) || v != var && occurs(var, body),
+        LetRec(v, exp, body) => v != var && (occurs(var, exp) || occurs(var, body)),
+        Select(_, table) => occurs(var, table),
+        Order(table, _) => occurs(var, table),
+        Where(l, r) => occurs(var, l) || occurs(var, r),
+        Union(l, r) => occurs(var, l) || occurs(var, r),
+        Difference(l, r) => occurs(var, l) || occurs(var, r),
+        Join(l, r) => occurs(var, l) || occurs(var, r),
+        Product(l, r) => occurs(var, l) || occurs(var, r),
+        Pivot(table, _, _) => occurs(var, table),
+        Table(_, exps) => exps.iter().any(|exp| occurs(var, exp)),
+        Or(l, r) => occurs(var, l) || occurs(var, r),
+        Equals(l, r) => occurs(var, l) || occurs(var, r),
+        And(l, r) => occurs(var, l) || occurs(var, r),
+        Exists(exp) => occurs(var, exp),
+        Not(exp) => occurs(var, exp),
+        Var(v) => v == var,
+        _ => false,
+    }
+}
+
+fn is_bool(exp: &Exp) -> bool {
+    matches!(
+        exp,
+        Bool(_) | Or(_, _) | Equals(_, _) | And(_, _) | Exists(_) | Not(_)
+    )
+}
+
+fn is_literal(exp: &Exp) -> bool {
+    matches!(exp, Bool(_) | Int(_) | Str(_) | Null)
+}
@@ -1,13 +1,13 @@
-use crate::{Exp, Exp::*};
+use crate::{Exp, Exp::*, Projection, SelectItem, SortKey};
 
 use nom::{
     branch::alt,
     bytes::complete::{is_not, tag, take_until, take_while},
     character::complete::{alpha1, alphanumeric1, char, digit1, multispace1},
-    combinator::{all_consuming, map, map_res, opt, recognize, value},
-    error::Error,
+    combinator::{all_consuming, map, not, opt, recognize, value},
+    error::{ErrorKind, ParseError},
     multi::many0,
-    sequence::{delimited, pair, preceded, tuple},
+    sequence::{delimited, pair, preceded, terminated, tuple},
     Finish, IResult,
 };
 
@@ -19,20 +19,51 @@ pub enum Bexp {
     Int(i64),
     Nil,
     Str(String),
+    Null,
     Var(String),
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Token {
+    Open,
+    Close,
+    Op(Op),
+    Atom(Bexp),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Error<'a> {
+    Nom(&'a str, ErrorKind),
+    IntOverflow(&'a str),
+    IdentTooLong(String),
+}
+
+impl<'a> ParseError<&'a str> for Error<'a> {
+    fn from_error_kind(input: &'a str, kind: ErrorKind) -> Self {
+        Error::Nom(input, kind)
+    }
+
+    fn append(_: &'a str, _: ErrorKind, other: Self) -> Self {
+        other
+    }
+}
+
+type Res<'a, T> = IResult<&'a str, T, Error<'a>>;
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd)]
 pub enum Op {
     In,
     Let,
     Select,
+    Order,
     Where,
     Union,
     Difference,
+    Join,
     Product,
     Table,
     Item,
+    As,
     Or,
     Equals,
     And,
@@ -51,12 +82,15 @@ impl Op {
             Op::In => Side::Right,
             Op::Let => Side::Right,
             Op::Select => Side::Right,
+            Op::Order => Side::Left,
             Op::Where => Side::Left,
             Op::Union => Side::Left,
             Op::Difference => Side::Left,
+            Op::Join => Side::Left,
             Op::Product => Side::Left,
             Op::Table => Side::Right,
             Op::Item => Side::Right,
+            Op::As => Side::Left,
             Op::Or => Side::Left,
             Op::And => Side::Left,
             Op::Equals => Side::Left,
@@ -65,44 +99,164 @@ impl Op {
     }
 }
 
+#[derive(Debug, Clone, Default)]
+pub struct ParseOptions {
+    /// Reject identifiers longer than this many characters. Built-in names
+    /// such as `row_number` are always allowed.
+    pub max_ident_len: Option<usize>,
+}
+
 pub fn parse(input: &str) -> Result<Exp, String> {
-    match all_consuming(parse_bexp)(input).finish() {
+    parse_with(input, &ParseOptions::default())
+}
+
+pub fn parse_with(input: &str, options: &ParseOptions) -> Result<Exp, String> {
+    match all_consuming(|input| parse_bexp(input, options))(input).finish() {
         Ok((_, bexp)) => parse_exp(bexp),
-        Result::Err(Error { input, code }) => Err(format!("{:?}, input: {:?}", code, input)),
+        Result::Err(Error::Nom(input, code)) => Err(format!("{:?}, input: {:?}", code, input)),
+        Result::Err(Error::IntOverflow(int)) => {
+            Err(format!("integer literal out of range: {}", int))
+        }
+        Result::Err(Error::IdentTooLong(var)) => Err(format!(
+            "identifier longer than {} characters: {}",
+            options.max_ident_len.unwrap_or_default(),
+            var
+        )),
+    }
+}
+
+// Splits as much of the input as possible into tokens, paired with their
+// byte offsets. Lexing stops quietly at the first thing that isn't a token,
+// such as an unterminated string.
+pub(crate) fn tokenize(input: &str) -> Vec<(usize, Token)> {
+    let mut tokens = vec![];
+    let mut rest = input;
+    loop {
+        if let Ok((next, _)) = junk(rest) {
+            rest = next;
+        }
+        let offset = input.len() - rest.len();
+        let token = alt((
+            value(Token::Open, char('(')),
+            value(Token::Close, char(')')),
+            map(parse_infix_op, Token::Op),
+            map(
+                alt((
+                    parse_bool,
+                    parse_int,
+                    parse_nil,
+                    parse_str,
+                    parse_null,
+                    |input| parse_var(input, &ParseOptions::default()),
+                )),
+                Token::Atom,
+            ),
+        ))(rest);
+        match token {
+            Ok((next, token)) => {
+                tokens.push((offset, token));
+                rest = next;
+            }
+            Result::Err(_) => return tokens,
+        }
     }
 }
 
+// The byte offset of a top-level statement, or of a row of the table literal
+// it binds. Statements are split by `;` outside of parentheses.
+pub(crate) fn statement_offset(input: &str, statement: usize, row: Option<usize>) -> usize {
+    let mut depth = 0;
+    let mut statements = vec![vec![]];
+    for (offset, token) in tokenize(input) {
+        // The depth a parenthesis is at is the depth outside it.
+        let token_depth = match token {
+            Token::Open => {
+                depth += 1;
+                depth - 1
+            }
+            Token::Close => {
+                depth -= 1;
+                depth
+            }
+            _ => depth,
+        };
+        if token == Token::Op(Op::In) && token_depth == 0 {
+            statements.push(vec![]);
+        } else if let Some(tokens) = statements.last_mut() {
+            tokens.push((offset, token_depth, token));
+        }
+    }
+    let Some(tokens) = statements.get(statement) else {
+        return input.len();
+    };
+    let start = tokens.first().map_or(input.len(), |(offset, _, _)| *offset);
+    let Some(row) = row else {
+        return start;
+    };
+
+    let is_op = |i: &usize, op| matches!(&tokens[*i], (_, 0, token) if *token == Token::Op(op));
+    let Some(value) = (0..tokens.len()).find(|i| is_op(i, Op::Let)) else {
+        return start;
+    };
+    let Some(colon) = (value..tokens.len()).find(|i| is_op(i, Op::Table)) else {
+        return start;
+    };
+    let width = (value..colon).filter(|i| is_op(i, Op::Item)).count() + 1;
+    // Each cell starts after the `:` or after a `,`.
+    std::iter::once(colon)
+        .chain((colon + 1..tokens.len()).filter(|i| is_op(i, Op::Item)))
+        .nth(row * width)
+        .and_then(|i| tokens.get(i + 1))
+        .map_or(start, |(offset, _, _)| *offset)
+}
+
 fn parse_exp(bexp: Bexp) -> Result<Exp, String> {
     match bexp {
         Bexp::Binary(l, op, r) => match op {
             Op::In => match *l {
-                Bexp::Binary(var, Op::Let, exp) => match parse_exp(*var)? {
-                    Var(var) => Ok(Let(
-                        var,
-                        Box::new(parse_exp(*exp)?),
-                        Box::new(parse_exp(*r)?),
-                    )),
-                    exp => Err(format!("expected var, got {:?}", exp)),
-                },
+                Bexp::Binary(var, Op::Let, exp) => {
+                    let exp = Box::new(parse_exp(*exp)?);
+                    let body = Box::new(parse_exp(*r)?);
+                    match rec_var(&var) {
+                        Some(var) => Ok(LetRec(var, exp, body)),
+                        None => match parse_exp(*var)? {
+                            Var(var) => Ok(Let(var, exp, body)),
+                            exp => Err(format!("expected var, got {:?}", exp)),
+                        },
+                    }
+                }
                 bexp => Err(format!("expected let, got {:?}", bexp)),
             },
             Op::Let => Err("let not allowed here".to_string()),
-            Op::Select => Ok(Select(parse_var_list(*l)?, Box::new(parse_exp(*r)?))),
+            Op::Select => Ok(Select(parse_select_list(*l)?, Box::new(parse_exp(*r)?))),
+            Op::Order => Ok(Order(Box::new(parse_exp(*l)?), parse_sort_keys(*r)?)),
             Op::Where => Ok(Where(Box::new(parse_exp(*l)?), Box::new(parse_exp(*r)?))),
             Op::Union => Ok(Union(Box::new(parse_exp(*l)?), Box::new(parse_exp(*r)?))),
             Op::Difference => Ok(Difference(
                 Box::new(parse_exp(*l)?),
                 Box::new(parse_exp(*r)?),
             )),
+            Op::Join => Ok(Join(Box::new(parse_exp(*l)?), Box::new(parse_exp(*r)?))),
             Op::Product => Ok(Product(Box::new(parse_exp(*l)?), Box::new(parse_exp(*r)?))),
             Op::Table => Ok(Table(parse_var_list(*l)?, parse_exp_list(*r)?)),
             Op::Item => Err("item not allowed here".to_string()),
+            Op::As => Err("as not allowed here".to_string()),
             Op::Or => Ok(Or(Box::new(parse_exp(*l)?), Box::new(parse_exp(*r)?))),
             Op::Equals => Ok(Equals(Box::new(parse_exp(*l)?), Box::new(parse_exp(*r)?))),
             Op::And => Ok(And(Box::new(parse_exp(*l)?), Box::new(parse_exp(*r)?))),
             Op::App => match parse_exp(*l)? {
                 Var(var) => match var.as_str() {
                     "not" => Ok(Not(Box::new(parse_exp(*r)?))),
+                    "exists" => Ok(Exists(Box::new(parse_exp(*r)?))),
+                    "pivot" => match *r {
+                        Bexp::Parens(args) => match parse_exp_list(*args)?.as_slice() {
+                            [table, Var(on), Var(value)] => {
+                                Ok(Pivot(Box::new(table.clone()), on.clone(), value.clone()))
+                            }
+                            _ => Err("expected pivot(table, var, var)".to_string()),
+                        },
+                        _ => Err("expected pivot(table, var, var)".to_string()),
+                    },
                     s => Err(format!("unknown function: {}", s)),
                 },
                 exp => Err(format!("cannot apply {:?}", exp)),
@@ -113,6 +267,7 @@ fn parse_exp(bexp: Bexp) -> Result<Exp, String> {
         Bexp::Int(int) => Ok(Int(int)),
         Bexp::Nil => Ok(Table(vec![], vec![])),
         Bexp::Str(str) => Ok(Str(str)),
+        Bexp::Null => Ok(Null),
         Bexp::Var(var) => Ok(Exp::Var(var)),
     }
 }
@@ -133,6 +288,85 @@ fn parse_var_list(bexp: Bexp) -> Result<Vec<String>, String> {
     }
 }
 
+fn parse_sort_keys(bexp: Bexp) -> Result<Vec<SortKey>, String> {
+    match bexp {
+        Bexp::Nil => Ok(vec![]),
+        Bexp::Binary(key, Op::Item, keys) => {
+            let mut result = vec![parse_sort_key(*key)?];
+            result.append(&mut parse_sort_keys(*keys)?);
+            Ok(result)
+        }
+        key => Ok(vec![parse_sort_key(key)?]),
+    }
+}
+
+fn parse_sort_key(bexp: Bexp) -> Result<SortKey, String> {
+    match bexp {
+        Bexp::Var(var) => Ok(SortKey::Column(var)),
+        bexp if is_call(&bexp, "random") => Ok(SortKey::Random),
+        Bexp::Binary(_, Op::App, _) => Err("expected variable or random()".to_string()),
+        _ => Err("expected variables".to_string()),
+    }
+}
+
+fn parse_select_list(bexp: Bexp) -> Result<Vec<SelectItem>, String> {
+    match bexp {
+        Bexp::Nil => Ok(vec![]),
+        Bexp::Binary(item, Op::Item, items) => {
+            let mut result = vec![parse_select_item(*item)?];
+            result.append(&mut parse_select_list(*items)?);
+            Ok(result)
+        }
+        item => Ok(vec![parse_select_item(item)?]),
+    }
+}
+
+fn parse_select_item(bexp: Bexp) -> Result<SelectItem, String> {
+    match bexp {
+        Bexp::Binary(source, Op::As, alias) => match *alias {
+            Bexp::Var(alias) => Ok(SelectItem {
+                source: parse_projection(*source)
+                    .map_err(|_| "expected variable as variable".to_string())?,
+                alias: Some(alias),
+            }),
+            _ => Err("expected variable as variable".to_string()),
+        },
+        source => Ok(SelectItem {
+            source: parse_projection(source)?,
+            alias: None,
+        }),
+    }
+}
+
+fn parse_projection(bexp: Bexp) -> Result<Projection, String> {
+    match bexp {
+        Bexp::Var(var) => Ok(Projection::Column(var)),
+        bexp if is_call(&bexp, "row_number") => Ok(Projection::RowNumber),
+        _ => Err("expected variable".to_string()),
+    }
+}
+
+// The variable bound by `rec var = exp; body`.
+fn rec_var(bexp: &Bexp) -> Option<String> {
+    match bexp {
+        Bexp::Binary(l, Op::App, r) if **l == Bexp::Var("rec".to_string()) => match &**r {
+            Bexp::Var(var) => Some(var.clone()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// Whether `bexp` is `f()`.
+fn is_call(bexp: &Bexp, f: &str) -> bool {
+    match bexp {
+        Bexp::Binary(l, Op::App, r) => {
+            **l == Bexp::Var(f.to_string()) && **r == Bexp::Parens(Box::new(Bexp::Nil))
+        }
+        _ => false,
+    }
+}
+
 fn parse_exp_list(bexp: Bexp) -> Result<Vec<Exp>, String> {
     match bexp {
         Bexp::Nil => Ok(vec![]),
@@ -146,10 +380,11 @@ fn parse_exp_list(bexp: Bexp) -> Result<Vec<Exp>, String> {
     }
 }
 
-fn parse_bexp(input: &str) -> IResult<&str, Bexp> {
+fn parse_bexp<'a>(input: &'a str, options: &ParseOptions) -> Res<'a, Bexp> {
+    let atom = |input| parse_atom(input, options);
     let (input, _) = junk(input)?;
-    let (input, first) = parse_atom(input)?;
-    let (input, rest) = many0(pair(preceded(junk, parse_op), preceded(junk, parse_atom)))(input)?;
+    let (input, first) = atom(input)?;
+    let (input, rest) = many0(pair(preceded(junk, parse_op), preceded(junk, atom)))(input)?;
     let (input, _) = junk(input)?;
 
     let exp = re_associate(left_associate(first, rest));
@@ -157,57 +392,114 @@ fn parse_bexp(input: &str) -> IResult<&str, Bexp> {
     Ok((input, exp))
 }
 
-fn parse_atom(input: &str) -> IResult<&str, Bexp> {
+fn parse_atom<'a>(input: &'a str, options: &ParseOptions) -> Res<'a, Bexp> {
     alt((
-        parse_parens,
+        |input| parse_parens(input, options),
         parse_bool,
         parse_int,
         parse_nil,
         parse_str,
-        parse_var,
+        parse_null,
+        |input| parse_var(input, options),
     ))(input)
 }
 
-fn parse_parens(input: &str) -> IResult<&str, Bexp> {
-    map(delimited(char('('), parse_bexp, char(')')), |exp| {
-        Bexp::Parens(Box::new(exp))
-    })(input)
-}
-
-fn parse_bool(input: &str) -> IResult<&str, Bexp> {
+// Empty parentheses stand for nil, so that `random()` reads as a call.
+fn parse_parens<'a>(input: &'a str, options: &ParseOptions) -> Res<'a, Bexp> {
     alt((
-        value(Bexp::Bool(true), tag("true")),
-        value(Bexp::Bool(false), tag("false")),
+        map(
+            delimited(char('('), |input| parse_bexp(input, options), char(')')),
+            |exp| Bexp::Parens(Box::new(exp)),
+        ),
+        value(
+            Bexp::Parens(Box::new(Bexp::Nil)),
+            tuple((char('('), junk, char(')'))),
+        ),
     ))(input)
 }
 
-fn parse_int(input: &str) -> IResult<&str, Bexp> {
-    map_res(recognize(pair(opt(tag("-")), digit1)), |s: &str| {
-        s.parse().map(Bexp::Int)
-    })(input)
+fn parse_bool(input: &str) -> Res<'_, Bexp> {
+    alt((
+        value(Bexp::Bool(true), keyword("true")),
+        value(Bexp::Bool(false), keyword("false")),
+    ))(input)
 }
 
-fn parse_nil(input: &str) -> IResult<&str, Bexp> {
-    value(Bexp::Nil, tag("nil"))(input)
+fn parse_int(input: &str) -> Res<'_, Bexp> {
+    let (input, int) = recognize(pair(opt(tag("-")), digit1))(input)?;
+    match int.parse() {
+        Ok(int) => Ok((input, Bexp::Int(int))),
+        Err(_) => Err(nom::Err::Failure(Error::IntOverflow(int))),
+    }
 }
 
-fn parse_str(input: &str) -> IResult<&str, Bexp> {
-    map(delimited(tag("'"), many0(is_not("'")), tag("'")), |s| {
-        Bexp::Str(s.concat())
-    })(input)
+fn parse_nil(input: &str) -> Res<'_, Bexp> {
+    value(Bexp::Nil, keyword("nil"))(input)
 }
 
-fn parse_var(input: &str) -> IResult<&str, Bexp> {
+// A quote is written twice inside a string.
+fn parse_str(input: &str) -> Res<'_, Bexp> {
     map(
-        recognize(pair(
-            alt((alpha1, tag("_"))),
-            many0(alt((alphanumeric1, tag("_")))),
-        )),
-        |s: &str| Bexp::Var(s.to_string()),
+        delimited(
+            tag("'"),
+            many0(alt((is_not("'"), value("'", tag("''"))))),
+            tag("'"),
+        ),
+        |s| Bexp::Str(s.concat()),
     )(input)
 }
 
-fn parse_op(input: &str) -> IResult<&str, Op> {
+fn parse_null(input: &str) -> Res<'_, Bexp> {
+    value(Bexp::Null, keyword("null"))(input)
+}
+
+// Names that `parse_exp` gives a meaning to when applied.
+const BUILTINS: [&str; 6] = ["exists", "not", "pivot", "random", "rec", "row_number"];
+
+fn parse_var<'a>(input: &'a str, options: &ParseOptions) -> Res<'a, Bexp> {
+    let (input, var) = alt((
+        // A backtick is written twice inside backticks.
+        map(
+            delimited(
+                char('`'),
+                many0(alt((is_not("`"), value("`", tag("``"))))),
+                char('`'),
+            ),
+            |parts| parts.concat(),
+        ),
+        map(
+            recognize(pair(
+                alt((alpha1, tag("_"))),
+                many0(alt((alphanumeric1, tag("_")))),
+            )),
+            str::to_string,
+        ),
+    ))(input)?;
+    match options.max_ident_len {
+        Some(max) if var.chars().count() > max && !BUILTINS.contains(&var.as_str()) => {
+            Err(nom::Err::Failure(Error::IdentTooLong(var)))
+        }
+        _ => Ok((input, Bexp::Var(var))),
+    }
+}
+
+// Words that can't be written as a plain variable.
+const KEYWORDS: [&str; 7] = ["true", "false", "nil", "null", "as", "order", "by"];
+
+pub(crate) fn is_plain_var(var: &str) -> bool {
+    let mut chars = var.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !KEYWORDS.contains(&var)
+}
+
+fn parse_op(input: &str) -> Res<'_, Op> {
+    alt((parse_infix_op, value(Op::App, tag(""))))(input)
+}
+
+fn parse_infix_op(input: &str) -> Res<'_, Op> {
     alt((
         value(Op::In, tag(";")),
         value(Op::Equals, tag("==")),
@@ -216,15 +508,24 @@ fn parse_op(input: &str) -> IResult<&str, Op> {
         value(Op::Where, tag("?")),
         value(Op::Union, tag("+")),
         value(Op::Difference, tag("-")),
+        value(Op::Join, tag("><")),
         value(Op::Product, tag("*")),
         value(Op::Table, tag(":")),
         value(Op::Item, tag(",")),
         value(Op::Or, tag("||")),
         value(Op::And, tag("&&")),
-        value(Op::App, tag("")),
+        value(Op::As, keyword("as")),
+        value(
+            Op::Order,
+            pair(keyword("order"), opt(pair(junk, keyword("by")))),
+        ),
     ))(input)
 }
 
+fn keyword<'a>(word: &'static str) -> impl FnMut(&'a str) -> Res<'a, &'a str> {
+    terminated(tag(word), not(alt((alphanumeric1, tag("_")))))
+}
+
 fn left_associate(first: Bexp, rest: Vec<(Op, Bexp)>) -> Bexp {
     rest.into_iter().fold(first, |acc, (op, exp)| {
         Bexp::Binary(Box::new(acc), op, Box::new(exp))
@@ -256,21 +557,25 @@ fn re_associate(exp: Bexp) -> Bexp {
     }
 }
 
-fn junk(input: &str) -> IResult<&str, ()> {
+pub(crate) fn is_junk(input: &str) -> bool {
+    all_consuming(junk)(input).is_ok()
+}
+
+fn junk(input: &str) -> Res<'_, ()> {
     value(
         (),
         many0(alt((whitespace, line_comment, multi_line_comment))),
     )(input)
 }
 
-fn whitespace(input: &str) -> IResult<&str, ()> {
+fn whitespace(input: &str) -> Res<'_, ()> {
     value((), multispace1)(input)
 }
 
-fn line_comment(input: &str) -> IResult<&str, ()> {
+fn line_comment(input: &str) -> Res<'_, ()> {
     value((), pair(tag("--"), take_while(|c| c != '\n')))(input)
 }
 
-fn multi_line_comment(input: &str) -> IResult<&str, ()> {
+fn multi_line_comment(input: &str) -> Res<'_, ()> {
     value((), tuple((tag("/*"), take_until("*/"), tag("*/"))))(input)
 }
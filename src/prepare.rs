@@ -0,0 +1,264 @@
+use crate::{
+    check, eval_with, parse, Env, EvalOptions, Exp, Exp::*, Projection, Schema, SchemaEnv, SortKey,
+    Type,
+};
+
+use std::{cmp::max, collections::HashMap};
+
+#[derive(Debug, Clone)]
+pub struct PreparedQuery {
+    exp: Exp,
+    schema: Schema,
+    schemas: SchemaEnv,
+}
+
+impl PreparedQuery {
+    /// The columns and types of the table the query returns.
+    pub fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    /// Runs the query against `env`, first checking any tables it was
+    /// prepared against.
+    pub fn execute(&self, env: &Env) -> Result<Exp, String> {
+        for (var, schema) in &self.schemas {
+            if let Some(exp) = env.get(var) {
+                check(schema, exp).map_err(|e| format!("{}: {}", var, e))?;
+            }
+        }
+        let options = EvalOptions {
+            schemas: self.schemas.clone(),
+            ..Default::default()
+        };
+        eval_with(&self.exp, env, &options).map(|(exp, _)| exp)
+    }
+}
+
+// Column and scalar types inferred without data. `None` is a type that isn't
+// known yet, such as a column of nulls, and is compatible with anything.
+#[derive(Debug, Clone)]
+enum Shape {
+    Table(Vec<(String, Option<Type>)>),
+    Scalar(Option<Type>),
+}
+
+type ShapeEnv = HashMap<String, Shape>;
+
+// Parses `src` and infers the schema of its result. Free variables must be
+// tables described by `schemas`.
+pub fn prepare(src: &str, schemas: &SchemaEnv) -> Result<PreparedQuery, String> {
+    let exp = parse(src)?;
+    let env = schemas
+        .iter()
+        .map(|(var, schema)| (var.clone(), Shape::Table(declared(schema))))
+        .collect();
+    let schema = infer_table(&exp, &env, schemas)?
+        .into_iter()
+        .map(|(column, ty)| match ty {
+            Some(ty) => Ok((column, ty)),
+            None => Err(format!("cannot infer type of column `{}`", column)),
+        })
+        .collect::<Result<_, String>>()?;
+    Ok(PreparedQuery {
+        exp,
+        schema,
+        schemas: schemas.clone(),
+    })
+}
+
+fn infer(exp: &Exp, env: &ShapeEnv, schemas: &SchemaEnv) -> Result<Shape, String> {
+    match exp {
+        Let(var, exp, body) => {
+            let shape = infer(exp, env, schemas)?;
+            if let (Some(schema), Shape::Table(columns)) = (schemas.get(var), &shape) {
+                unify_columns(&declared(schema), columns).map_err(|e| format!("{}: {}", var, e))?;
+            }
+            let mut env = env.clone();
+            env.insert(var.clone(), shape);
+            infer(body, &env, schemas)
+        }
+        LetRec(var, exp, body) => {
+            let Union(base, step) = &**exp else {
+                return Err(format!("expected rec {} = base + step", var));
+            };
+            let columns = infer_table(base, env, schemas)?;
+            if let Some(schema) = schemas.get(var) {
+                unify_columns(&declared(schema), &columns)
+                    .map_err(|e| format!("{}: {}", var, e))?;
+            }
+            let mut env = env.clone();
+            env.insert(var.clone(), Shape::Table(columns.clone()));
+            let columns = unify_columns(&columns, &infer_table(step, &env, schemas)?)?;
+            env.insert(var.clone(), Shape::Table(columns));
+            infer(body, &env, schemas)
+        }
+        Select(items, table) => {
+            let columns = infer_table(table, env, schemas)?;
+            items
+                .iter()
+                .map(|item| {
+                    let ty = match &item.source {
+                        Projection::Column(column) => column_type(&columns, column, "select")?,
+                        Projection::RowNumber => Some(Type::Int),
+                    };
+                    Ok((item.name().to_string(), ty))
+                })
+                .collect::<Result<_, String>>()
+                .map(Shape::Table)
+        }
+        Order(table, keys) => {
+            let columns = infer_table(table, env, schemas)?;
+            for key in keys {
+                if let SortKey::Column(key) = key {
+                    column_type(&columns, key, "order by")?;
+                }
+            }
+            Ok(Shape::Table(columns))
+        }
+        Where(table, cond) => {
+            let columns = infer_table(table, env, schemas)?;
+            let env = columns
+                .iter()
+                .map(|(column, ty)| (column.clone(), Shape::Scalar(*ty)))
+                .collect();
+            match infer_scalar(cond, &env, schemas)? {
+                None | Some(Type::Bool) => Ok(Shape::Table(columns)),
+                Some(ty) => Err(format!("expected boolean in where clause, found {}", ty)),
+            }
+        }
+        Union(l, r) | Difference(l, r) => {
+            let l = infer_table(l, env, schemas)?;
+            let r = infer_table(r, env, schemas)?;
+            unify_columns(&l, &r).map(Shape::Table)
+        }
+        Join(l, r) => {
+            let mut columns = infer_table(l, env, schemas)?;
+            for (column, ty) in infer_table(r, env, schemas)? {
+                match columns.iter_mut().find(|(c, _)| *c == column) {
+                    Some((_, l_ty)) => *l_ty = unify(*l_ty, ty, &column)?,
+                    None => columns.push((column, ty)),
+                }
+            }
+            Ok(Shape::Table(columns))
+        }
+        Product(l, r) => Ok(Shape::Table(
+            [infer_table(l, env, schemas)?, infer_table(r, env, schemas)?].concat(),
+        )),
+        Pivot(_, _, _) => Err("cannot infer the columns of a pivot without data".to_string()),
+        Table(vars, exps) => {
+            let mut columns = vars
+                .iter()
+                .map(|var| (var.clone(), None))
+                .collect::<Vec<_>>();
+            for row in exps.chunks(max(vars.len(), 1)) {
+                for ((column, ty), exp) in columns.iter_mut().zip(row) {
+                    *ty = unify(*ty, infer_scalar(exp, env, schemas)?, column)?;
+                }
+            }
+            Ok(Shape::Table(columns))
+        }
+        Or(l, r) | And(l, r) => {
+            for exp in [l, r] {
+                match infer_scalar(exp, env, schemas)? {
+                    None | Some(Type::Bool) => {}
+                    Some(ty) => return Err(format!("expected boolean, found {}", ty)),
+                }
+            }
+            Ok(Shape::Scalar(Some(Type::Bool)))
+        }
+        Equals(l, r) => match (
+            infer_scalar(l, env, schemas)?,
+            infer_scalar(r, env, schemas)?,
+        ) {
+            (Some(l), Some(r)) if l != r => Err(format!("cannot compare {} with {}", l, r)),
+            _ => Ok(Shape::Scalar(Some(Type::Bool))),
+        },
+        Exists(table) => {
+            infer_table(table, env, schemas)?;
+            Ok(Shape::Scalar(Some(Type::Bool)))
+        }
+        Not(exp) => match infer_scalar(exp, env, schemas)? {
+            None | Some(Type::Bool) => Ok(Shape::Scalar(Some(Type::Bool))),
+            Some(ty) => Err(format!("expected boolean, found {}", ty)),
+        },
+        Bool(_) | Int(_) | Str(_) => Ok(Shape::Scalar(Type::of(exp))),
+        Null => Ok(Shape::Scalar(None)),
+        Var(var) => env
+            .get(var)
+            .cloned()
+            .ok_or_else(|| format!("unknown variable: {}", var)),
+    }
+}
+
+fn infer_table(
+    exp: &Exp,
+    env: &ShapeEnv,
+    schemas: &SchemaEnv,
+) -> Result<Vec<(String, Option<Type>)>, String> {
+    match infer(exp, env, schemas)? {
+        Shape::Table(columns) => Ok(columns),
+        Shape::Scalar(_) => Err(format!("expected table, found {}", exp)),
+    }
+}
+
+fn infer_scalar(exp: &Exp, env: &ShapeEnv, schemas: &SchemaEnv) -> Result<Option<Type>, String> {
+    match infer(exp, env, schemas)? {
+        Shape::Scalar(ty) => Ok(ty),
+        Shape::Table(_) => Err(format!("expected value, found table {}", exp)),
+    }
+}
+
+fn declared(schema: &Schema) -> Vec<(String, Option<Type>)> {
+    schema
+        .iter()
+        .map(|(column, ty)| (column.clone(), Some(*ty)))
+        .collect()
+}
+
+fn column_type(
+    columns: &[(String, Option<Type>)],
+    column: &str,
+    context: &str,
+) -> Result<Option<Type>, String> {
+    columns
+        .iter()
+        .find(|(c, _)| c == column)
+        .map(|(_, ty)| *ty)
+        .ok_or_else(|| format!("no column `{}` to {}", column, context))
+}
+
+fn unify_columns(
+    l: &[(String, Option<Type>)],
+    r: &[(String, Option<Type>)],
+) -> Result<Vec<(String, Option<Type>)>, String> {
+    if !l.iter().map(|(c, _)| c).eq(r.iter().map(|(c, _)| c)) {
+        return Err(format!(
+            "expected columns {}, found {}",
+            column_names(l),
+            column_names(r)
+        ));
+    }
+    l.iter()
+        .zip(r)
+        .map(|((column, l), (_, r))| Ok((column.clone(), unify(*l, *r, column)?)))
+        .collect()
+}
+
+fn unify(l: Option<Type>, r: Option<Type>, column: &str) -> Result<Option<Type>, String> {
+    match (l, r) {
+        (Some(l), Some(r)) if l != r => Err(format!(
+            "expected {} in column `{}`, found {}",
+            l, column, r
+        )),
+        (Some(ty), _) | (_, Some(ty)) => Ok(Some(ty)),
+        (None, None) => Ok(None),
+    }
+}
+
+fn column_names(columns: &[(String, Option<Type>)]) -> String {
+    columns
+        .iter()
+        .map(|(column, _)| column.as_str())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
@@ -0,0 +1,117 @@
+use crate::{eval::check_rows, parse, Exp, Exp::*};
+
+use std::{cmp::max, collections::HashMap, fmt};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Type {
+    Bool,
+    Int,
+    Str,
+}
+
+pub type Schema = Vec<(String, Type)>;
+
+pub type SchemaEnv = HashMap<String, Schema>;
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Type::Bool => write!(f, "bool"),
+            Type::Int => write!(f, "int"),
+            Type::Str => write!(f, "str"),
+        }
+    }
+}
+
+impl Type {
+    pub fn of(exp: &Exp) -> Option<Type> {
+        match exp {
+            Bool(_) => Some(Type::Bool),
+            Int(_) => Some(Type::Int),
+            Str(_) => Some(Type::Str),
+            _ => None,
+        }
+    }
+}
+
+// A schema file is a chain of bindings ending in nil, where each binding is
+// a table header with a single row of type names:
+//
+// Staff = id, name, employed : int, str, bool;
+// nil
+pub fn parse_schemas(text: &str) -> Result<SchemaEnv, String> {
+    let mut schemas = SchemaEnv::new();
+    let mut exp = parse(text)?;
+    loop {
+        match exp {
+            Let(var, table, body) => {
+                schemas.insert(var.clone(), parse_schema(&var, *table)?);
+                exp = *body;
+            }
+            Table(vars, exps) if vars.is_empty() && exps.is_empty() => return Ok(schemas),
+            exp => return Err(format!("expected schema declaration, got {}", exp)),
+        }
+    }
+}
+
+fn parse_schema(var: &str, table: Exp) -> Result<Schema, String> {
+    let Table(vars, exps) = table else {
+        return Err(format!("expected table header for {}", var));
+    };
+    if vars.len() != exps.len() {
+        return Err(format!("expected one type per column for {}", var));
+    }
+    vars.into_iter()
+        .zip(exps)
+        .map(|(column, exp)| match exp {
+            Var(ty) if ty == "bool" => Ok((column, Type::Bool)),
+            Var(ty) if ty == "int" => Ok((column, Type::Int)),
+            Var(ty) if ty == "str" => Ok((column, Type::Str)),
+            exp => Err(format!("unknown type {} for {}.{}", exp, var, column)),
+        })
+        .collect()
+}
+
+pub fn check(schema: &Schema, exp: &Exp) -> Result<(), String> {
+    check_row(schema, exp).map_err(|(_, e)| e)
+}
+
+// Like `check`, but a type error also gives the index of the row it's in.
+pub(crate) fn check_row(schema: &Schema, exp: &Exp) -> Result<(), (Option<usize>, String)> {
+    let Table(vars, exps) = exp else {
+        return Err((None, "expected table".to_string()));
+    };
+    if !vars.iter().eq(schema.iter().map(|(column, _)| column)) {
+        return Err((
+            None,
+            format!(
+                "expected columns {}, found {}",
+                schema
+                    .iter()
+                    .map(|(column, _)| column.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                vars.join(", ")
+            ),
+        ));
+    }
+    let width = max(vars.len(), 1);
+    check_rows(vars, exps).map_err(|e| (Some(exps.len() / width), e))?;
+    for (i, row) in exps.chunks(width).enumerate() {
+        for ((column, ty), exp) in schema.iter().zip(row) {
+            if *exp != Null && Type::of(exp) != Some(*ty) {
+                return Err((
+                    Some(i),
+                    format!(
+                        "row {}: expected {} in column `{}`, found {}",
+                        i + 1,
+                        ty,
+                        column,
+                        exp
+                    ),
+                ));
+            }
+        }
+    }
+    Ok(())
+}
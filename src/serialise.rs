@@ -1,13 +1,22 @@
 use crate::{
+    parse::is_plain_var,
     Bexp,
     Exp::{self, *},
-    Op, Side,
+    Op, Projection, SelectItem, Side, SortKey,
 };
 
+use std::{cmp::max, fmt};
+
 pub fn serialise(exp: Exp) -> String {
     serialise_bexp(serialise_exp(exp))
 }
 
+impl fmt::Display for Exp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", serialise(self.clone()))
+    }
+}
+
 fn serialise_exp(exp: Exp) -> Bexp {
     match exp {
         Let(var, exp, body) => Bexp::Binary(
@@ -19,11 +28,29 @@ fn serialise_exp(exp: Exp) -> Bexp {
             Op::In,
             Box::new(with_parens(*body, Op::In, Side::Right)),
         ),
+        LetRec(var, exp, body) => Bexp::Binary(
+            Box::new(Bexp::Binary(
+                Box::new(Bexp::Binary(
+                    Box::new(Bexp::Var("rec".to_string())),
+                    Op::App,
+                    Box::new(Bexp::Var(var)),
+                )),
+                Op::Let,
+                Box::new(with_parens(*exp, Op::Let, Side::Right)),
+            )),
+            Op::In,
+            Box::new(with_parens(*body, Op::In, Side::Right)),
+        ),
         Select(l, r) => Bexp::Binary(
-            Box::new(serialise_var_list(l)),
+            Box::new(serialise_select_list(l)),
             Op::Select,
             Box::new(with_parens(*r, Op::Select, Side::Right)),
         ),
+        Order(table, keys) => Bexp::Binary(
+            Box::new(with_parens(*table, Op::Order, Side::Left)),
+            Op::Order,
+            Box::new(serialise_sort_keys(keys)),
+        ),
         Where(l, r) => Bexp::Binary(
             Box::new(with_parens(*l, Op::Where, Side::Left)),
             Op::Where,
@@ -39,11 +66,25 @@ fn serialise_exp(exp: Exp) -> Bexp {
             Op::Difference,
             Box::new(with_parens(*r, Op::Difference, Side::Right)),
         ),
+        Join(l, r) => Bexp::Binary(
+            Box::new(with_parens(*l, Op::Join, Side::Left)),
+            Op::Join,
+            Box::new(with_parens(*r, Op::Join, Side::Right)),
+        ),
         Product(l, r) => Bexp::Binary(
             Box::new(with_parens(*l, Op::Product, Side::Left)),
             Op::Product,
             Box::new(with_parens(*r, Op::Product, Side::Right)),
         ),
+        Pivot(table, on, value) => Bexp::Binary(
+            Box::new(Bexp::Var("pivot".to_string())),
+            Op::App,
+            Box::new(Bexp::Parens(Box::new(serialise_exp_list(vec![
+                *table,
+                Var(on),
+                Var(value),
+            ])))),
+        ),
         Table(vars, exps) => {
             if vars.is_empty() && exps.is_empty() {
                 Bexp::Nil
@@ -70,18 +111,35 @@ fn serialise_exp(exp: Exp) -> Bexp {
             Op::And,
             Box::new(with_parens(*r, Op::And, Side::Right)),
         ),
+        Exists(exp) => Bexp::Binary(
+            Box::new(Bexp::Var("exists".to_string())),
+            Op::App,
+            Box::new(with_parens(*exp, Op::App, Side::Right)),
+        ),
         Not(exp) => Bexp::Binary(
             Box::new(Bexp::Var("not".to_string())),
             Op::App,
-            Box::new(with_parens(*exp, Op::App, Side::Left)),
+            Box::new(with_parens(*exp, Op::App, Side::Right)),
         ),
         Bool(bool) => Bexp::Bool(bool),
         Int(int) => Bexp::Int(int),
         Str(str) => Bexp::Str(str),
+        Null => Bexp::Null,
         Var(var) => Bexp::Var(var),
     }
 }
 
+// The header and rows of a table, for laying it out over several lines.
+pub(crate) fn serialise_table_rows(vars: Vec<String>, exps: Vec<Exp>) -> (String, Vec<String>) {
+    let width = max(vars.len(), 1);
+    let header = serialise_bexp(serialise_var_list(vars));
+    let rows = exps
+        .chunks(width)
+        .map(|row| serialise_bexp(serialise_exp_list(row.to_vec())))
+        .collect();
+    (header, rows)
+}
+
 fn serialise_var_list(mut vars: Vec<String>) -> Bexp {
     if vars.is_empty() {
         Bexp::Nil
@@ -93,6 +151,55 @@ fn serialise_var_list(mut vars: Vec<String>) -> Bexp {
     }
 }
 
+fn serialise_sort_keys(mut keys: Vec<SortKey>) -> Bexp {
+    let serialise_key = |key| match key {
+        SortKey::Column(var) => Bexp::Var(var),
+        SortKey::Random => serialise_call("random"),
+    };
+    if keys.is_empty() {
+        Bexp::Nil
+    } else {
+        let first = serialise_key(keys.remove(0));
+        keys.into_iter().fold(first, |acc, key| {
+            Bexp::Binary(Box::new(acc), Op::Item, Box::new(serialise_key(key)))
+        })
+    }
+}
+
+fn serialise_select_list(mut items: Vec<SelectItem>) -> Bexp {
+    if items.is_empty() {
+        Bexp::Nil
+    } else {
+        let first = serialise_select_item(items.remove(0));
+        items.into_iter().fold(first, |acc, item| {
+            Bexp::Binary(
+                Box::new(acc),
+                Op::Item,
+                Box::new(serialise_select_item(item)),
+            )
+        })
+    }
+}
+
+fn serialise_select_item(item: SelectItem) -> Bexp {
+    let source = match item.source {
+        Projection::Column(column) => Bexp::Var(column),
+        Projection::RowNumber => serialise_call("row_number"),
+    };
+    match item.alias {
+        Some(alias) => Bexp::Binary(Box::new(source), Op::As, Box::new(Bexp::Var(alias))),
+        None => source,
+    }
+}
+
+fn serialise_call(f: &str) -> Bexp {
+    Bexp::Binary(
+        Box::new(Bexp::Var(f.to_string())),
+        Op::App,
+        Box::new(Bexp::Parens(Box::new(Bexp::Nil))),
+    )
+}
+
 fn serialise_exp_list(mut exps: Vec<Exp>) -> Bexp {
     if exps.is_empty() {
         Bexp::Nil
@@ -118,6 +225,8 @@ fn with_parens(exp: Exp, parent: Op, side: Side) -> Bexp {
                 bexp
             }
         }
+        // `not -1` would parse as `not - 1`.
+        Bexp::Int(int) if int < 0 && parent == Op::App => Bexp::Parens(Box::new(bexp)),
         _ => bexp,
     }
 }
@@ -130,12 +239,15 @@ fn serialise_bexp(exp: Bexp) -> String {
             serialise_op(op),
             serialise_bexp(*r)
         ),
+        Bexp::Parens(bexp) if *bexp == Bexp::Nil => "()".to_string(),
         Bexp::Parens(bexp) => format!("({})", serialise_bexp(*bexp),),
         Bexp::Bool(bool) => bool.to_string(),
         Bexp::Int(int) => int.to_string(),
         Bexp::Nil => "nil".to_string(),
-        Bexp::Str(str) => format!("'{}'", str),
-        Bexp::Var(var) => var,
+        Bexp::Str(str) => format!("'{}'", str.replace('\'', "''")),
+        Bexp::Null => "null".to_string(),
+        Bexp::Var(var) if is_plain_var(&var) => var,
+        Bexp::Var(var) => format!("`{}`", var.replace('`', "``")),
     }
 }
 
@@ -144,12 +256,15 @@ fn serialise_op(op: Op) -> &'static str {
         Op::In => "; ",
         Op::Let => " = ",
         Op::Select => " <- ",
+        Op::Order => " order ",
         Op::Where => " ? ",
         Op::Union => " + ",
         Op::Difference => " - ",
+        Op::Join => " >< ",
         Op::Product => " * ",
         Op::Table => " : ",
         Op::Item => ", ",
+        Op::As => " as ",
         Op::Or => " || ",
         Op::Equals => " == ",
         Op::And => " && ",
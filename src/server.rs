@@ -36,11 +36,14 @@ async fn handle_connection(mut stream: TcpStream, conf: Arc<Server>) -> Result<(
     let parsed = parse(&text)?;
 
     let reads = analyse_reads(&parsed, &empty());
+    let writes = analyse_writes(&parsed);
+    for var in reads.iter().chain(&writes) {
+        check_filename(var)?;
+    }
     let env = read_env(&conf.directory, &reads).await?;
 
     let (result, env) = eval(&parsed, &env)?;
 
-    let writes = analyse_writes(&parsed);
     let env = env
         .into_iter()
         .filter(|(k, _)| writes.contains(k))
@@ -94,17 +97,33 @@ async fn write_env(dir: &str, env: &Env) -> io::Result<()> {
     Ok(())
 }
 
+// Variables are stored as files named after them, so a quoted name mustn't
+// reach outside the directory.
+fn check_filename(var: &str) -> Result<(), String> {
+    if var.is_empty() || var.contains(['/', '\\', '\0']) || var.contains("..") {
+        return Err(format!("cannot store variable `{}` on the server", var));
+    }
+    Ok(())
+}
+
 fn analyse_reads(exp: &Exp, defined: &HashSet<String>) -> HashSet<String> {
     match exp {
         Exp::Let(var, exp, body) => union(
             analyse_reads(exp, defined),
             analyse_reads(body, &union(single(var), defined.clone())),
         ),
+        Exp::LetRec(var, exp, body) => {
+            let defined = union(single(var), defined.clone());
+            union(analyse_reads(exp, &defined), analyse_reads(body, &defined))
+        }
         Exp::Select(_, r) => analyse_reads(r, defined),
+        Exp::Order(table, _) => analyse_reads(table, defined),
         Exp::Where(l, r) => union(analyse_reads(l, defined), analyse_reads(r, defined)),
         Exp::Union(l, r) => union(analyse_reads(l, defined), analyse_reads(r, defined)),
         Exp::Difference(l, r) => union(analyse_reads(l, defined), analyse_reads(r, defined)),
+        Exp::Join(l, r) => union(analyse_reads(l, defined), analyse_reads(r, defined)),
         Exp::Product(l, r) => union(analyse_reads(l, defined), analyse_reads(r, defined)),
+        Exp::Pivot(table, _, _) => analyse_reads(table, defined),
         Exp::Table(_, r) => r
             .iter()
             .flat_map(|exp| analyse_reads(exp, defined))
@@ -112,6 +131,7 @@ fn analyse_reads(exp: &Exp, defined: &HashSet<String>) -> HashSet<String> {
         Exp::Or(l, r) => union(analyse_reads(l, defined), analyse_reads(r, defined)),
         Exp::Equals(l, r) => union(analyse_reads(l, defined), analyse_reads(r, defined)),
         Exp::And(l, r) => union(analyse_reads(l, defined), analyse_reads(r, defined)),
+        Exp::Exists(exp) => analyse_reads(exp, defined),
         Exp::Not(exp) => analyse_reads(exp, defined),
         Exp::Var(var) if !defined.contains(var) => single(var),
         _ => empty(),
@@ -120,7 +140,9 @@ fn analyse_reads(exp: &Exp, defined: &HashSet<String>) -> HashSet<String> {
 
 fn analyse_writes(exp: &Exp) -> HashSet<String> {
     match exp {
-        Exp::Let(var, _, body) => union(single(var), analyse_writes(body)),
+        Exp::Let(var, _, body) | Exp::LetRec(var, _, body) => {
+            union(single(var), analyse_writes(body))
+        }
         _ => empty(),
     }
 }
@@ -0,0 +1,219 @@
+use crate::{parse, serialise, Exp, Exp::*};
+
+use std::{
+    cmp::{max, Ordering},
+    collections::hash_map::RandomState,
+    env,
+    fs::{self, File, OpenOptions},
+    hash::BuildHasher,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::PathBuf,
+    process,
+};
+
+// A temporary file of rows, removed when dropped. Rows are written once and
+// can then be read back any number of times.
+pub(crate) struct Spill {
+    path: PathBuf,
+    writer: BufWriter<File>,
+}
+
+impl Spill {
+    // The name is random and the file must not already exist, so another
+    // user can't have planted it in the shared temporary directory.
+    pub(crate) fn new() -> Result<Spill, String> {
+        let random = RandomState::new();
+        let mut attempt = 0;
+        loop {
+            let name = format!(
+                "sdb-spill-{}-{:016x}",
+                process::id(),
+                random.hash_one(attempt)
+            );
+            let path = env::temp_dir().join(name);
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(file) => {
+                    return Ok(Spill {
+                        path,
+                        writer: BufWriter::new(file),
+                    })
+                }
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists && attempt < 100 => attempt += 1,
+                Err(e) => return Err(spill_error(e)),
+            }
+        }
+    }
+
+    pub(crate) fn write_row(&mut self, row: &[Exp]) -> Result<(), String> {
+        write_len(&mut self.writer, row.len())?;
+        row.iter()
+            .try_for_each(|exp| write_exp(&mut self.writer, exp))
+    }
+
+    pub(crate) fn rows(&mut self) -> Result<Rows, String> {
+        self.writer.flush().map_err(spill_error)?;
+        let file = File::open(&self.path).map_err(spill_error)?;
+        Ok(Rows {
+            reader: BufReader::new(file),
+        })
+    }
+}
+
+impl Drop for Spill {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+pub(crate) struct Rows {
+    reader: BufReader<File>,
+}
+
+impl Iterator for Rows {
+    type Item = Result<Vec<Exp>, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let len = match read_u64(&mut self.reader) {
+            Ok(len) => len,
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return None,
+            Err(e) => return Some(Err(spill_error(e))),
+        };
+        Some((0..len).map(|_| read_exp(&mut self.reader)).collect())
+    }
+}
+
+// An external merge sort of a table's cells. Runs of `run_len` rows are cut
+// from the end of `cells`, sorted and spilled, releasing the memory they took,
+// then merged into the result. Each row is paired with `key` of its index for
+// `cmp`. Ties go to the earlier run, so the sort is stable.
+pub(crate) fn sort(
+    mut cells: Vec<Exp>,
+    width: usize,
+    run_len: usize,
+    key: impl Fn(usize) -> u64,
+    cmp: impl Fn(&(u64, &[Exp]), &(u64, &[Exp])) -> Ordering,
+) -> Result<Vec<Exp>, String> {
+    let len = cells.len();
+    let mut spills = vec![];
+    let mut end = len / width;
+    while end > 0 {
+        let start = end.saturating_sub(max(run_len, 1));
+        let run_cells = cells.split_off(start * width);
+        cells.shrink_to_fit();
+        let mut run = run_cells
+            .chunks(width)
+            .enumerate()
+            .map(|(i, row)| (key(start + i), row))
+            .collect::<Vec<_>>();
+        run.sort_by(&cmp);
+        let mut spill = Spill::new()?;
+        for (key, row) in run {
+            spill.write_row(&[[Int(key as i64)].as_slice(), row].concat())?;
+        }
+        spills.push(spill);
+        end = start;
+    }
+    spills.reverse();
+
+    let mut runs = spills
+        .iter_mut()
+        .map(|spill| spill.rows())
+        .collect::<Result<Vec<_>, String>>()?;
+    let mut heads = runs
+        .iter_mut()
+        .map(next_keyed)
+        .collect::<Result<Vec<_>, String>>()?;
+    let mut sorted = Vec::with_capacity(len);
+    loop {
+        let min = heads
+            .iter()
+            .enumerate()
+            .filter_map(|(i, head)| head.as_ref().map(|(key, row)| (i, (*key, row.as_slice()))))
+            .reduce(|min, next| match cmp(&next.1, &min.1) {
+                Ordering::Less => next,
+                _ => min,
+            })
+            .map(|(i, _)| i);
+        let Some(i) = min else {
+            return Ok(sorted);
+        };
+        let next = next_keyed(&mut runs[i])?;
+        if let Some((_, row)) = std::mem::replace(&mut heads[i], next) {
+            sorted.extend(row);
+        }
+    }
+}
+
+fn next_keyed(rows: &mut Rows) -> Result<Option<(u64, Vec<Exp>)>, String> {
+    match rows.next().transpose()? {
+        Some(mut row) => match row.remove(0) {
+            Int(key) => Ok(Some((key as u64, row))),
+            _ => Err("spill: expected sort key".to_string()),
+        },
+        None => Ok(None),
+    }
+}
+
+// Cells are tagged. Values are stored directly and anything else, such as a
+// nested table, is stored as source text.
+fn write_exp(writer: &mut impl Write, exp: &Exp) -> Result<(), String> {
+    match exp {
+        Null => writer.write_all(&[0]),
+        Bool(bool) => writer.write_all(&[1, *bool as u8]),
+        Int(int) => writer
+            .write_all(&[2])
+            .and_then(|_| writer.write_all(&int.to_le_bytes())),
+        Str(str) => writer
+            .write_all(&[3])
+            .and_then(|_| write_bytes(writer, str)),
+        exp => writer
+            .write_all(&[4])
+            .and_then(|_| write_bytes(writer, &serialise(exp.clone()))),
+    }
+    .map_err(spill_error)
+}
+
+fn read_exp(reader: &mut impl Read) -> Result<Exp, String> {
+    let mut tag = [0];
+    reader.read_exact(&mut tag).map_err(spill_error)?;
+    match tag[0] {
+        0 => Ok(Null),
+        1 => {
+            let mut bool = [0];
+            reader.read_exact(&mut bool).map_err(spill_error)?;
+            Ok(Bool(bool[0] != 0))
+        }
+        2 => Ok(Int(read_u64(reader).map_err(spill_error)? as i64)),
+        3 => Ok(Str(read_string(reader)?)),
+        4 => parse(&read_string(reader)?),
+        tag => Err(format!("spill: unknown tag {}", tag)),
+    }
+}
+
+fn write_len(writer: &mut impl Write, len: usize) -> Result<(), String> {
+    writer
+        .write_all(&(len as u64).to_le_bytes())
+        .map_err(spill_error)
+}
+
+fn write_bytes(writer: &mut impl Write, str: &str) -> io::Result<()> {
+    writer.write_all(&(str.len() as u64).to_le_bytes())?;
+    writer.write_all(str.as_bytes())
+}
+
+fn read_u64(reader: &mut impl Read) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_string(reader: &mut impl Read) -> Result<String, String> {
+    let len = read_u64(reader).map_err(spill_error)?;
+    let mut bytes = vec![0; len as usize];
+    reader.read_exact(&mut bytes).map_err(spill_error)?;
+    String::from_utf8(bytes).map_err(|e| format!("spill: {}", e))
+}
+
+fn spill_error(e: io::Error) -> String {
+    format!("spill: {}", e)
+}
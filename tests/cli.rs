@@ -0,0 +1,185 @@
+use std::{
+    fs,
+    net::TcpStream,
+    path::PathBuf,
+    process::{Command, Stdio},
+    thread,
+    time::Duration,
+};
+
+fn write(name: &str, text: &str) -> PathBuf {
+    let path = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(name);
+    fs::write(&path, text).unwrap();
+    path
+}
+
+fn run(args: &[&str]) -> (String, String) {
+    let output = Command::new(env!("CARGO_BIN_EXE_sdb"))
+        .args(args)
+        .output()
+        .unwrap();
+    (
+        String::from_utf8(output.stdout).unwrap(),
+        String::from_utf8(output.stderr).unwrap(),
+    )
+}
+
+fn succeeds(args: &[&str]) -> bool {
+    Command::new(env!("CARGO_BIN_EXE_sdb"))
+        .args(args)
+        .status()
+        .unwrap()
+        .success()
+}
+
+#[test]
+fn test_schema() {
+    let schema = write(
+        "schema.sdb",
+        r#"
+Staff = id, name, employed : int, str, bool;
+nil
+"#,
+    );
+    let valid = write(
+        "valid.sdb",
+        r#"
+Staff =
+  id, name, employed :
+  1, 'Alice', true,
+  2, 'Bob', false;
+Staff
+"#,
+    );
+    let invalid = write(
+        "invalid.sdb",
+        r#"
+Staff =
+  id, name, employed :
+  1, 'Alice', true,
+  'two', 'Bob', false;
+Staff
+"#,
+    );
+    let schema = schema.to_str().unwrap();
+    let valid = valid.to_str().unwrap();
+    let invalid = invalid.to_str().unwrap();
+
+    assert_eq!(
+        run(&["run", "--schema", schema, valid]),
+        (
+            "id, name, employed : 1, 'Alice', true, 2, 'Bob', false\n".to_string(),
+            "".to_string()
+        )
+    );
+
+    assert_eq!(
+        run(&["run", "--schema", schema, invalid]),
+        (
+            "".to_string(),
+            format!(
+                "Error evaluating program: {}: line 5: Staff: row 2: expected int in column `id`, found 'two'\n",
+                invalid
+            )
+        )
+    );
+}
+
+#[test]
+fn test_fmt() {
+    let messy = r#"-- Staff table
+
+
+Staff =   id,name,employed:
+1,'Alice',true,2,'Bob',   false; -- two rows
+/* names */
+Names = id,name<-Staff;
+x = (y = 1;y);
+
+Staff?employed  -- done
+"#;
+    let formatted = r#"-- Staff table
+
+Staff =
+  id, name, employed :
+  1, 'Alice', true,
+  2, 'Bob', false; -- two rows
+/* names */
+Names = id, name <- Staff;
+x = (y = 1; y);
+
+Staff ? employed -- done
+"#;
+    let path = write("messy.sdb", messy);
+    let path_str = path.to_str().unwrap();
+
+    assert!(!succeeds(&["fmt", "--check", path_str]));
+    assert_eq!(fs::read_to_string(&path).unwrap(), messy);
+
+    assert!(succeeds(&["fmt", path_str]));
+    assert_eq!(fs::read_to_string(&path).unwrap(), formatted);
+    assert!(succeeds(&["fmt", "--check", path_str]));
+
+    let nested = write("nested.sdb", "x = (rec Y = (a : 1) + Y; Y);\nx\n");
+    let nested_str = nested.to_str().unwrap();
+    assert!(succeeds(&["fmt", nested_str]));
+    assert_eq!(
+        fs::read_to_string(&nested).unwrap(),
+        "x = (rec Y = a : 1 + Y; Y);\nx\n"
+    );
+    assert!(succeeds(&["run", nested_str]));
+
+    let rows = write(
+        "rows.sdb",
+        "Staff = id, name : -- people\n1,'Alice', -- founder\n2,'Bob';\nStaff\n",
+    );
+    let rows_str = rows.to_str().unwrap();
+    assert!(succeeds(&["fmt", rows_str]));
+    assert_eq!(
+        fs::read_to_string(&rows).unwrap(),
+        "Staff =\n  id, name : -- people\n  1, 'Alice', -- founder\n  2, 'Bob';\nStaff\n"
+    );
+    assert!(succeeds(&["fmt", "--check", rows_str]));
+
+    let inner = write("inner.sdb", "x = 1 -- one\n + 2;\nx\n");
+    assert_eq!(
+        run(&["fmt", inner.to_str().unwrap()]),
+        (
+            "".to_string(),
+            format!(
+                "Error formatting program: {}: cannot format a comment inside an expression\n",
+                inner.to_str().unwrap()
+            )
+        )
+    );
+}
+
+#[test]
+fn test_server_filenames() {
+    let root = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("server");
+    let _ = fs::remove_dir_all(&root);
+    let db = root.join("db");
+    let port = "23457";
+    let mut server = Command::new(env!("CARGO_BIN_EXE_sdb"))
+        .args(["start", "-d", db.to_str().unwrap(), "-p", port])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    let url = format!("localhost:{}", port);
+    while TcpStream::connect(&url).is_err() {
+        thread::sleep(Duration::from_millis(50));
+    }
+
+    let stored = run(&["run", "-s", &url, "-e", "x = 1; x"]);
+    let escaped = run(&["run", "-s", &url, "-e", "`../pwned` = 1; nil"]);
+    let read = run(&["run", "-s", &url, "-e", "`../db/x`"]);
+    server.kill().unwrap();
+    server.wait().unwrap();
+
+    assert_eq!(stored, ("1\n".to_string(), "".to_string()));
+    assert!(db.join("x").exists());
+    assert_eq!(escaped, ("\n".to_string(), "".to_string()));
+    assert!(!root.join("pwned").exists());
+    assert_eq!(read, ("\n".to_string(), "".to_string()));
+}
@@ -0,0 +1,110 @@
+use sdb::{
+    complete, read_eval, Completion,
+    CompletionKind::{self, *},
+    Env,
+};
+
+fn env() -> Env {
+    let (_, env) = read_eval(
+        r#"
+Staff = id, name, employed : 1, 'Alice', true;
+Sizes = size : 'Small';
+nil
+"#,
+        &Env::new(),
+    )
+    .unwrap();
+    env
+}
+
+fn completions(src: &str) -> Vec<(CompletionKind, String)> {
+    complete(src, src.len(), &env())
+        .into_iter()
+        .map(|Completion { kind, text }| (kind, text))
+        .collect()
+}
+
+#[test]
+fn test_variable() {
+    assert_eq!(completions("Sta"), vec![(Variable, "Staff".to_string())]);
+    assert_eq!(
+        completions("x = 1; Si"),
+        vec![(Variable, "Sizes".to_string())]
+    );
+    assert_eq!(
+        completions("extra = 1; e"),
+        vec![
+            (Variable, "extra".to_string()),
+            (Keyword, "exists".to_string())
+        ]
+    );
+    assert_eq!(
+        completions("Staff + Siz"),
+        vec![(Variable, "Sizes".to_string())]
+    );
+    assert_eq!(
+        complete("Sta + Sizes", 3, &env()),
+        vec![Completion {
+            kind: Variable,
+            text: "Staff".to_string()
+        }]
+    );
+    assert_eq!(
+        complete("x = ·Sta", 9, &env()),
+        vec![Completion {
+            kind: Variable,
+            text: "Staff".to_string()
+        }]
+    );
+}
+
+#[test]
+fn test_column() {
+    assert_eq!(
+        completions("name <- Staff ? "),
+        vec![
+            (Column, "employed".to_string()),
+            (Column, "id".to_string()),
+            (Column, "name".to_string()),
+            (Variable, "Sizes".to_string()),
+            (Variable, "Staff".to_string()),
+            (Keyword, "exists".to_string()),
+            (Keyword, "false".to_string()),
+            (Keyword, "nil".to_string()),
+            (Keyword, "not".to_string()),
+            (Keyword, "null".to_string()),
+            (Keyword, "pivot".to_string()),
+            (Keyword, "rec".to_string()),
+            (Keyword, "true".to_string()),
+        ]
+    );
+    assert_eq!(
+        completions("name <- Staff ? id == 1 && (n"),
+        vec![
+            (Column, "name".to_string()),
+            (Keyword, "nil".to_string()),
+            (Keyword, "not".to_string()),
+            (Keyword, "null".to_string()),
+        ]
+    );
+    assert_eq!(
+        completions("(Staff * Sizes) ? s"),
+        vec![(Column, "size".to_string())]
+    );
+    assert_eq!(
+        completions("Staff ? id == 1; e"),
+        vec![(Keyword, "exists".to_string())]
+    );
+}
+
+#[test]
+fn test_keyword() {
+    assert_eq!(completions("no"), vec![(Keyword, "not".to_string())]);
+    assert_eq!(completions("name a"), vec![(Keyword, "as".to_string())]);
+    assert!(completions("Staff ? id == ").contains(&(Column, "id".to_string())));
+    assert!(completions("Staff ").contains(&(Keyword, "?".to_string())));
+    assert!(completions("not ").contains(&(Variable, "Staff".to_string())));
+    assert!(completions("exists ").contains(&(Variable, "Sizes".to_string())));
+    assert!(completions("Staff ? not ").contains(&(Column, "employed".to_string())));
+    assert!(!completions("not ").contains(&(Keyword, "?".to_string())));
+}
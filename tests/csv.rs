@@ -0,0 +1,81 @@
+use sdb::{parse, table_from_csv, table_to_csv, CsvOptions, Exp::*};
+
+#[test]
+fn test_from_csv() {
+    assert_eq!(
+        table_from_csv(
+            "id,name,note\n1,Alice,\n2,\"Smith, Bob\",\"said \"\"hi\"\"\"\r\n",
+            &CsvOptions::default()
+        ),
+        Ok(Table(
+            vec!["id".to_string(), "name".to_string(), "note".to_string()],
+            vec![
+                Int(1),
+                Str("Alice".to_string()),
+                Null,
+                Int(2),
+                Str("Smith, Bob".to_string()),
+                Str("said \"hi\"".to_string()),
+            ]
+        ))
+    );
+    assert_eq!(
+        table_from_csv("a,b\n1\n", &CsvOptions::default()),
+        Err("record 1: expected 2 fields, found 1".to_string())
+    );
+    assert_eq!(
+        table_from_csv("a\n\"1\n", &CsvOptions::default()),
+        Err("unterminated quoted field".to_string())
+    );
+}
+
+#[test]
+fn test_bools() {
+    let csv = "name,employed\nAlice,true\nBob,false\nCarol,TRUE\n";
+
+    assert_eq!(
+        table_from_csv(csv, &CsvOptions::default()),
+        Ok(parse("name, employed : 'Alice', 'true', 'Bob', 'false', 'Carol', 'TRUE'").unwrap())
+    );
+
+    let options = CsvOptions { infer_bools: true };
+    let table = table_from_csv(csv, &options).unwrap();
+    assert_eq!(
+        table,
+        parse("name, employed : 'Alice', true, 'Bob', false, 'Carol', 'TRUE'").unwrap()
+    );
+    assert_eq!(table_to_csv(&table), Ok(csv.to_string()));
+    assert_eq!(
+        table_from_csv(&table_to_csv(&table).unwrap(), &options),
+        Ok(table)
+    );
+}
+
+#[test]
+fn test_round_trip() {
+    let table = parse("a, b : 'true', '12', '', null, 'x,y', -3").unwrap();
+    let csv = table_to_csv(&table).unwrap();
+    assert_eq!(csv, "a,b\n\"true\",\"12\"\n\"\",\n\"x,y\",-3\n");
+    assert_eq!(
+        table_from_csv(&csv, &CsvOptions { infer_bools: true }),
+        Ok(table)
+    );
+    assert_eq!(
+        table_to_csv(&parse("a : (b : 1)").unwrap()),
+        Err("cannot write b : 1 to csv".to_string())
+    );
+}
+
+#[test]
+fn test_csv_source() {
+    let table = table_from_csv("name,,`x`\nO'Brien,1,2\n", &CsvOptions::default()).unwrap();
+    assert_eq!(
+        table,
+        Table(
+            vec!["name".to_string(), "".to_string(), "`x`".to_string()],
+            vec![Str("O'Brien".to_string()), Int(1), Int(2)]
+        )
+    );
+    assert_eq!(table.to_string(), "name, ``, ```x``` : 'O''Brien', 1, 2");
+    assert_eq!(parse(&table.to_string()), Ok(table));
+}
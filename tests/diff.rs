@@ -0,0 +1,73 @@
+use sdb::{read_eval, table_diff, Env, Exp::*, TableDiff};
+
+fn table(src: &str) -> sdb::Exp {
+    read_eval(src, &Env::new()).unwrap().0
+}
+
+#[test]
+fn test_diff() {
+    let old = table("id, name, employed : 1, 'Alice', true, 2, 'Bob', true, 3, 'Charlie', false");
+    let new = table(
+        "id, name, employed : 1, 'Alice', true, 2, 'Bob', false, 3, 'Charlie', false, 4, 'Dana', true",
+    );
+    assert_eq!(
+        table_diff(&old, &new, &["id"]),
+        Ok(TableDiff {
+            added: table("id, name, employed : 4, 'Dana', true"),
+            removed: table("id, name, employed : nil"),
+            changed: vec![(
+                vec![Int(2), Str("Bob".to_string()), Bool(true)],
+                vec![Int(2), Str("Bob".to_string()), Bool(false)]
+            )],
+        })
+    );
+
+    let diff = table_diff(&new, &old, &["id", "name"]).unwrap();
+    assert_eq!(diff.added, table("id, name, employed : nil"));
+    assert_eq!(diff.removed, table("id, name, employed : 4, 'Dana', true"));
+    assert_eq!(diff.changed.len(), 1);
+
+    assert_eq!(table_diff(&old, &old, &["id"]).unwrap().changed, vec![]);
+}
+
+#[test]
+fn test_diff_errors() {
+    let old = table("id, name : 1, 'Alice', 1, 'Bob'");
+    let new = table("id, name : 1, 'Alice'");
+    assert_eq!(
+        table_diff(&old, &new, &["id"]),
+        Err("duplicate key 1 in diff".to_string())
+    );
+    assert_eq!(
+        table_diff(&new, &new, &["size"]),
+        Err("no column `size` to diff by".to_string())
+    );
+    assert_eq!(
+        table_diff(&new, &table("id : 1"), &["id"]),
+        Err("cannot diff columns id, name with id".to_string())
+    );
+    let short = table("a, b : 1, 2, 3");
+    assert_eq!(
+        table_diff(&short, &short, &["b"]),
+        Err("row 2 has 1 of 2 values".to_string())
+    );
+}
+
+#[test]
+fn test_diff_large() {
+    let rows = |range: std::ops::Range<i64>, offset| {
+        let exps = range.flat_map(|i| [Int(i), Int(i % 7 + offset)]).collect();
+        Table(vec!["id".to_string(), "v".to_string()], exps)
+    };
+    let old = rows(0..50_000, 0);
+    let new = rows(10_000..60_000, 0);
+    let diff = table_diff(&old, &new, &["id"]).unwrap();
+    assert_eq!(diff.added, rows(50_000..60_000, 0));
+    assert_eq!(diff.removed, rows(0..10_000, 0));
+    assert_eq!(diff.changed, vec![]);
+
+    let changed = table_diff(&old, &rows(0..50_000, 1), &["id"])
+        .unwrap()
+        .changed;
+    assert_eq!(changed.len(), 50_000);
+}
@@ -1,4 +1,4 @@
-use sdb::{read_eval, Env, Exp::*};
+use sdb::{eval_with, parse, parse_schemas, read_eval, read_eval_lines, Env, EvalOptions, Exp::*};
 
 macro_rules! run {
     ($input:expr, $output:expr) => {{
@@ -26,6 +26,19 @@ fn test_select() {
         "foo <- name, id : 'Alice', 1, 'Bob', 2",
         Table(vec!["foo".to_string()], vec![])
     );
+
+    run!(
+        "id as n, name as fullname <- name, id : 'Alice', 1, 'Bob', 2",
+        Table(
+            vec!["n".to_string(), "fullname".to_string()],
+            vec![
+                Int(1),
+                Str("Alice".to_string()),
+                Int(2),
+                Str("Bob".to_string())
+            ]
+        )
+    );
 }
 
 #[test]
@@ -94,6 +107,31 @@ fn test_union() {
     );
 }
 
+#[test]
+fn test_set_order() {
+    let program = r#"
+Left = id : 3, 1, 2;
+Right = id : 5, 1, 4;
+(Left + Right) + (Left - Right)
+"#;
+    let expected = Table(
+        vec!["id".to_string()],
+        vec![
+            Int(3),
+            Int(1),
+            Int(2),
+            Int(5),
+            Int(1),
+            Int(4),
+            Int(3),
+            Int(2),
+        ],
+    );
+    for _ in 0..10 {
+        run!(program, expected.clone());
+    }
+}
+
 #[test]
 fn test_product() {
     run!(
@@ -143,6 +181,81 @@ Colors * Sizes
     );
 }
 
+#[test]
+fn test_join() {
+    let tables = r#"
+Staff =
+  id, name :
+  1, 'Alice',
+  2, 'Bob',
+  3, 'Charlie';
+
+Roles =
+  id, role :
+  1, 'Admin',
+  1, 'Editor',
+  3, 'Viewer';
+
+Sizes = size : 'Small', 'Large';
+"#;
+
+    run!(
+        &format!("{} Staff >< Roles", tables),
+        Table(
+            vec!["id".to_string(), "name".to_string(), "role".to_string()],
+            vec![
+                Int(1),
+                Str("Alice".to_string()),
+                Str("Admin".to_string()),
+                Int(1),
+                Str("Alice".to_string()),
+                Str("Editor".to_string()),
+                Int(3),
+                Str("Charlie".to_string()),
+                Str("Viewer".to_string()),
+            ]
+        )
+    );
+
+    run!(
+        &format!("{} Roles >< Staff ? role == 'Viewer'", tables),
+        Table(
+            vec!["id".to_string(), "role".to_string(), "name".to_string()],
+            vec![
+                Int(3),
+                Str("Viewer".to_string()),
+                Str("Charlie".to_string())
+            ]
+        )
+    );
+
+    run!(
+        &format!("{} (id <- Staff) >< Sizes", tables),
+        Table(
+            vec!["id".to_string(), "size".to_string()],
+            vec![
+                Int(1),
+                Str("Small".to_string()),
+                Int(1),
+                Str("Large".to_string()),
+                Int(2),
+                Str("Small".to_string()),
+                Int(2),
+                Str("Large".to_string()),
+                Int(3),
+                Str("Small".to_string()),
+                Int(3),
+                Str("Large".to_string()),
+            ]
+        )
+    );
+
+    assert_eq!(
+        read_eval(&format!("{} Staff >< (id, role : 1)", tables), &Env::new()),
+        Err("row 1 has 1 of 2 values".to_string())
+    );
+}
+
 #[test]
 fn test_difference() {
     run!(
@@ -180,3 +293,351 @@ Left - Right
         )
     );
 }
+
+#[test]
+fn test_order() {
+    let staff = "Staff = name, id, employed : 'Bob', 2, true, 'Alice', 3, false, 'Alice', 1, true;";
+
+    run!(
+        &format!("{} name <- Staff order id", staff),
+        Table(
+            vec!["name".to_string()],
+            vec![
+                Str("Alice".to_string()),
+                Str("Bob".to_string()),
+                Str("Alice".to_string())
+            ]
+        )
+    );
+
+    run!(
+        &format!("{} id <- Staff order name, id", staff),
+        Table(vec!["id".to_string()], vec![Int(1), Int(3), Int(2)])
+    );
+
+    run!(
+        &format!("{} id <- Staff ? employed order by name", staff),
+        Table(vec!["id".to_string()], vec![Int(1), Int(2)])
+    );
+
+    run!(
+        "x : 'a', 2, null, true, 1 order x",
+        Table(
+            vec!["x".to_string()],
+            vec![Null, Bool(true), Int(1), Int(2), Str("a".to_string())]
+        )
+    );
+
+    assert_eq!(
+        read_eval("x : 1 order y", &Env::new()),
+        Err("no column `y` to order by".to_string())
+    );
+    assert_eq!(
+        read_eval("a, b : 1, 2, 3 order b", &Env::new()),
+        Err("row 2 has 1 of 2 values".to_string())
+    );
+}
+
+#[test]
+fn test_row_number() {
+    run!(
+        "row_number() as n, name <- name : 'Charlie', 'Alice', 'Bob' order name",
+        Table(
+            vec!["n".to_string(), "name".to_string()],
+            vec![
+                Int(1),
+                Str("Alice".to_string()),
+                Int(2),
+                Str("Bob".to_string()),
+                Int(3),
+                Str("Charlie".to_string())
+            ]
+        )
+    );
+
+    run!(
+        "name, row_number() <- name, id : 'Alice', 1, 'Bob', 2 ? id == 2",
+        Table(
+            vec!["name".to_string(), "row_number".to_string()],
+            vec![Str("Bob".to_string()), Int(1)]
+        )
+    );
+}
+
+#[test]
+fn test_order_random() {
+    let shuffle = |seed| {
+        let options = EvalOptions {
+            seed: Some(seed),
+            ..Default::default()
+        };
+        let exp = parse("x : 1, 2, 3, 4, 5, 6, 7, 8 order by random()").unwrap();
+        eval_with(&exp, &Env::new(), &options).map(|(exp, _)| exp)
+    };
+
+    assert_eq!(
+        shuffle(1),
+        Ok(Table(
+            vec!["x".to_string()],
+            vec![
+                Int(3),
+                Int(5),
+                Int(7),
+                Int(4),
+                Int(1),
+                Int(2),
+                Int(8),
+                Int(6)
+            ]
+        ))
+    );
+    assert_eq!(shuffle(1), shuffle(1));
+    assert_ne!(shuffle(1), shuffle(2));
+}
+
+#[test]
+fn test_spill() {
+    let rows = (0..20)
+        .map(|i| format!("{}, '{}', {}", i % 3, (b'a' + i % 7) as char, i))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let t = format!("T = x, y, z : {}; ", rows);
+    let run = |src: &str, spill_threshold| {
+        let options = EvalOptions {
+            seed: Some(7),
+            spill_threshold,
+            ..Default::default()
+        };
+        let exp = parse(&format!("{}{}", t, src)).unwrap();
+        eval_with(&exp, &Env::new(), &options).map(|(exp, _)| exp)
+    };
+
+    for src in [
+        "T order x",
+        "T order by y, x",
+        "T order random()",
+        "T order x, random()",
+        "T * (a : 1, 2, 3)",
+        "(a : 1, 2, 3) * T",
+        "(T ? x == 0) * (T ? x == 1) order z",
+        "(a, t : 2, (b : 1), 1, (b : 2), 0, null) order a",
+    ] {
+        let in_memory = run(src, None);
+        assert!(in_memory.is_ok());
+        assert_eq!(run(src, Some(2)), in_memory, "{}", src);
+        assert_eq!(run(src, Some(0)), in_memory, "{}", src);
+    }
+
+    let prefix = format!("sdb-spill-{}-", std::process::id());
+    assert!(!std::fs::read_dir(std::env::temp_dir())
+        .unwrap()
+        .any(|entry| entry
+            .unwrap()
+            .file_name()
+            .to_string_lossy()
+            .starts_with(&prefix)));
+}
+
+#[test]
+fn test_pivot() {
+    run!(
+        r#"
+Sales =
+  region, month, amount :
+  'North', 'Jan', 10,
+  'North', 'Feb', 20,
+  'South', 'Jan', 30;
+
+pivot(Sales, month, amount)
+"#,
+        Table(
+            vec!["region".to_string(), "Jan".to_string(), "Feb".to_string()],
+            vec![
+                Str("North".to_string()),
+                Int(10),
+                Int(20),
+                Str("South".to_string()),
+                Int(30),
+                Null,
+            ]
+        )
+    );
+
+    assert_eq!(
+        read_eval("pivot((k, v : 'x', 1), month, v)", &Env::new()),
+        Err("no column `month` to pivot".to_string())
+    );
+    assert_eq!(
+        read_eval("pivot((k, v : 'x', 1, 'x', 2), k, v)", &Env::new()),
+        Err("duplicate value for 'x' in pivot".to_string())
+    );
+    assert_eq!(
+        read_eval("pivot((k, r, v : 'x', 1, 2, 'y'), k, v)", &Env::new()),
+        Err("row 2 has 1 of 3 values".to_string())
+    );
+    assert_eq!(
+        read_eval("pivot((k, r, v : 'r', 'x', 1), k, v)", &Env::new()),
+        Err("duplicate column `r` in pivot".to_string())
+    );
+    assert_eq!(
+        read_eval("pivot((k, v : 'true', 1, true, 2), k, v)", &Env::new()),
+        Err("duplicate column `true` in pivot".to_string())
+    );
+}
+
+#[test]
+fn test_exists() {
+    run!("exists(x : 1, 2 ? x == 2)", Bool(true));
+    run!("exists(x : 1, 2 ? x == 3)", Bool(false));
+    run!("exists nil", Bool(false));
+    run!("T = x, y : 1, 2, 3, 4; exists(y <- T ? x == 3)", Bool(true));
+
+    // The second row would fail the condition, so this only succeeds if the
+    // scan stops at the first match.
+    run!("exists(x : true, 1 ? x)", Bool(true));
+}
+
+#[test]
+fn test_rec() {
+    let edges = "Edges = from, to : 1, 2, 2, 3, 3, 4, 5, 5;";
+    let reach = "rec Reach = Edges + (from, to <- (from, to as mid <- Edges) >< (from as mid, to <- Reach));";
+
+    run!(
+        &format!("{} {} Reach ? from == 1", edges, reach),
+        Table(
+            vec!["from".to_string(), "to".to_string()],
+            vec![Int(1), Int(2), Int(1), Int(3), Int(1), Int(4)]
+        )
+    );
+    run!(
+        &format!("{} {} to <- Reach ? from == 5", edges, reach),
+        Table(vec!["to".to_string()], vec![Int(5)])
+    );
+
+    assert_eq!(
+        read_eval("rec X = (n : 1) + ((n : 2) - X); X", &Env::new()),
+        Err("recursion for X is not monotone".to_string())
+    );
+    assert_eq!(
+        read_eval("rec X = n : 1; X", &Env::new()),
+        Err("expected rec X = base + step".to_string())
+    );
+
+    // Each step nests the previous table in a new row, so there is no
+    // fixpoint.
+    let options = EvalOptions {
+        max_iterations: Some(5),
+        ..Default::default()
+    };
+    assert_eq!(
+        eval_with(
+            &parse("rec X = (t : nil) + (X + (t : X)); X").unwrap(),
+            &Env::new(),
+            &options
+        )
+        .map(|(exp, _)| exp),
+        Err("X did not reach a fixpoint".to_string())
+    );
+}
+
+#[test]
+fn test_strict() {
+    let strict = EvalOptions {
+        strict: true,
+        ..Default::default()
+    };
+
+    for input in ["1 == 'one'", "1 || false", "true && 'yes'"] {
+        assert!(read_eval(input, &Env::new()).is_ok());
+    }
+    run!("1 == 'one'", Bool(false));
+    run!("1 || false", Bool(false));
+
+    assert_eq!(
+        eval_with(&parse("1 == 'one'").unwrap(), &Env::new(), &strict),
+        Err("cannot compare int with str in strict mode".to_string())
+    );
+    assert_eq!(
+        eval_with(&parse("1 || false").unwrap(), &Env::new(), &strict),
+        Err("expected boolean in strict mode, found int".to_string())
+    );
+    assert_eq!(
+        eval_with(&parse("1 == null").unwrap(), &Env::new(), &strict),
+        Ok((Bool(false), Env::new()))
+    );
+    assert_eq!(
+        eval_with(
+            &parse("1 == 2 || 'a' == 'a'").unwrap(),
+            &Env::new(),
+            &strict
+        ),
+        Ok((Bool(true), Env::new()))
+    );
+}
+
+#[test]
+fn test_schema() {
+    let options = EvalOptions {
+        schemas: parse_schemas("Staff = id, name : int, str; nil").unwrap(),
+        ..Default::default()
+    };
+    let run = |input| eval_with(&parse(input).unwrap(), &Env::new(), &options).map(|(exp, _)| exp);
+
+    assert_eq!(
+        run("Staff = id, name : 1, 'Alice'; Staff = Staff + id, name : 2, 'Bob'; id <- Staff"),
+        Ok(Table(vec!["id".to_string()], vec![Int(1), Int(2)]))
+    );
+    assert_eq!(
+        run("Staff = id, name : 1, 'Alice'; Staff = Staff + id, name : 'Bob', 2; Staff"),
+        Err("Staff: row 2: expected int in column `id`, found 'Bob'".to_string())
+    );
+    assert_eq!(
+        run("Staff = id, name : null, 'Alice'; name <- Staff"),
+        Ok(Table(
+            vec!["name".to_string()],
+            vec![Str("Alice".to_string())]
+        ))
+    );
+    assert_eq!(
+        run("Staff = name, id : 'Alice', 1; Staff"),
+        Err("Staff: expected columns id, name, found name, id".to_string())
+    );
+    assert_eq!(
+        run("Staff = id, name : 1, 'a', 2; Staff"),
+        Err("Staff: row 2 has 1 of 2 values".to_string())
+    );
+    assert_eq!(
+        parse_schemas("Staff = id : float; nil"),
+        Err("unknown type float for Staff.id".to_string())
+    );
+}
+
+#[test]
+fn test_schema_lines() {
+    let options = EvalOptions {
+        schemas: parse_schemas("Staff = id, name : int, str; nil").unwrap(),
+        ..Default::default()
+    };
+    let run = |input| read_eval_lines(input, &Env::new(), &options).map(|(exp, _)| exp);
+
+    assert_eq!(
+        run("Staff =\n  id, name :\n  (1), 'Alice',\n  'two', 'Bob';\nStaff"),
+        Err("line 4: Staff: row 2: expected int in column `id`, found 'two'".to_string())
+    );
+    assert_eq!(
+        run("Staff =\n  id, name :\n  1, 'Alice',\n  2;\nStaff"),
+        Err("line 4: Staff: row 2 has 1 of 2 values".to_string())
+    );
+    assert_eq!(
+        run("Staff = id, name : 1, 'Alice';\nStaff = Staff +\n  (id, name : 'Bob', 2);\nStaff"),
+        Err("line 2: Staff: row 2: expected int in column `id`, found 'Bob'".to_string())
+    );
+    assert_eq!(
+        run("-- staff\nStaff = id, name : 1, 'Alice';\nx = (z = 1; y); Staff"),
+        Err("line 3: Variable `y` not defined".to_string())
+    );
+    assert_eq!(
+        run("Staff = id, name : 1, 'Alice';\nid <- Staff"),
+        Ok(Table(vec!["id".to_string()], vec![Int(1)]))
+    );
+}
@@ -0,0 +1,105 @@
+use sdb::{minimize, minimize_traced, parse, Rule};
+
+macro_rules! run {
+    ($input:expr, $output:expr) => {{
+        let minimized = minimize(parse($input).unwrap());
+        assert_eq!(minimized, parse($output).unwrap());
+    }};
+}
+
+#[test]
+fn test_fold() {
+    run!("not true", "false");
+    run!("not (1 == 1)", "false");
+    run!("'a' == 'b' || x", "false || x");
+    run!("x == 1 || false", "x == 1");
+    run!("true && (x == 1)", "x == 1");
+    run!("x && false", "x && false");
+    run!("Staff ? true || x", "Staff");
+    run!(
+        "Staff ? not false && (name == 'Alice')",
+        "Staff ? name == 'Alice'"
+    );
+    run!("a : 1 == 1, not false", "a : true, true");
+}
+
+#[test]
+fn test_dead_let() {
+    run!("x = 1; y = 2; x", "x = 1; x");
+    run!("x = 1; x = 2; x", "x = 2; x");
+    run!("x = 1; x = x + x; x", "x = 1; x = x + x; x");
+    run!("x = 1; y = (x = 2; x); y", "y = (x = 2; x); y");
+}
+
+#[test]
+fn test_reassociate() {
+    run!("a + (b + (c + d))", "a + b + c + d");
+    run!("a * (b * c)", "a * b * c");
+    run!("a || (b || c)", "a || b || c");
+    run!("a && (b && (c && d))", "a && b && c && d");
+    run!("a - (b - c)", "a - (b - c)");
+    run!("a == (b == c)", "a == (b == c)");
+    run!("rec X = a + (X + b); X", "rec X = a + (X + b); X");
+    run!("rec X = a + (b + (X + c)); X", "rec X = a + (b + X + c); X");
+}
+
+#[test]
+fn test_equivalent() {
+    let a = minimize(parse("unused = Staff; Staff + (Staff + Staff) ? not false").unwrap());
+    let b = minimize(parse("Staff + Staff + Staff").unwrap());
+    assert_eq!(a, b);
+
+    let a = minimize(parse("Staff - (Staff - Staff)").unwrap());
+    let b = minimize(parse("Staff - Staff - Staff").unwrap());
+    assert_ne!(a, b);
+}
+
+#[test]
+fn test_trace() {
+    let (exp, trace) = minimize_traced(parse("unused = 1; Staff ? not false && (1 == 1)").unwrap());
+    assert_eq!(exp, parse("Staff").unwrap());
+    assert_eq!(
+        trace.iter().map(|(rule, _, _)| *rule).collect::<Vec<_>>(),
+        vec![
+            Rule::FoldNot,
+            Rule::FoldEquals,
+            Rule::FoldAnd,
+            Rule::FoldWhere,
+            Rule::DeadLet
+        ]
+    );
+    assert_eq!(
+        trace[2],
+        (
+            Rule::FoldAnd,
+            parse("true && true").unwrap(),
+            parse("true").unwrap()
+        )
+    );
+
+    let (_, trace) = minimize_traced(parse("a + (b + (c + d))").unwrap());
+    assert_eq!(
+        trace,
+        vec![
+            (
+                Rule::Reassociate,
+                parse("b + (c + d)").unwrap(),
+                parse("b + c + d").unwrap()
+            ),
+            (
+                Rule::Reassociate,
+                parse("a + (b + c + d)").unwrap(),
+                parse("(a + (b + c)) + d").unwrap()
+            ),
+            (
+                Rule::Reassociate,
+                parse("a + (b + c)").unwrap(),
+                parse("a + b + c").unwrap()
+            ),
+        ]
+    );
+
+    let (exp, trace) = minimize_traced(parse("a - b").unwrap());
+    assert_eq!(exp, parse("a - b").unwrap());
+    assert_eq!(trace, vec![]);
+}
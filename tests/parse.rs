@@ -1,4 +1,4 @@
-use sdb::{parse, Exp::*};
+use sdb::{parse, parse_with, Exp::*, ParseOptions, Projection, SelectItem, SortKey};
 
 #[test]
 fn test_bool() {
@@ -10,6 +10,14 @@ fn test_bool() {
 fn test_int() {
     assert_eq!(parse("123"), Ok(Int(123)));
     assert_eq!(parse("-42"), Ok(Int(-42)));
+    assert_eq!(
+        parse("1234567890123456789012345"),
+        Err("integer literal out of range: 1234567890123456789012345".to_string())
+    );
+    assert_eq!(
+        parse("x : 1, -1234567890123456789012345, 'a'"),
+        Err("integer literal out of range: -1234567890123456789012345".to_string())
+    );
 }
 
 #[test]
@@ -19,10 +27,244 @@ fn test_str() {
     assert_eq!(parse("'hello world'"), Ok(Str("hello world".to_string())));
 }
 
+#[test]
+fn test_null() {
+    assert_eq!(parse("null"), Ok(Null));
+    assert_eq!(parse("nullable"), Ok(Var("nullable".to_string())));
+    assert_eq!(parse("null_"), Ok(Var("null_".to_string())));
+    assert_eq!(
+        parse("x: null"),
+        Ok(Table(vec!["x".to_string()], vec![Null]))
+    );
+}
+
 #[test]
 fn test_var() {
     assert_eq!(parse("x"), Ok(Var("x".to_string())));
     assert_eq!(parse("_x_1"), Ok(Var("_x_1".to_string())));
+    assert_eq!(parse("trueish"), Ok(Var("trueish".to_string())));
+    assert_eq!(parse("nillable"), Ok(Var("nillable".to_string())));
+}
+
+#[test]
+fn test_quoted_var() {
+    assert_eq!(parse("`first name`"), Ok(Var("first name".to_string())));
+    assert_eq!(parse("`order`"), Ok(Var("order".to_string())));
+    assert_eq!(parse("`null`"), Ok(Var("null".to_string())));
+    assert_eq!(
+        parse("`first name`: 'Alice'"),
+        Ok(Table(
+            vec!["first name".to_string()],
+            vec![Str("Alice".to_string())]
+        ))
+    );
+    assert_eq!(
+        parse("`first name` as `order`, id <- Staff order `first name`"),
+        Ok(Select(
+            vec![
+                SelectItem::aliased("first name", "order"),
+                SelectItem::new("id")
+            ],
+            Box::new(Order(
+                Box::new(Var("Staff".to_string())),
+                vec![SortKey::Column("first name".to_string())]
+            ))
+        ))
+    );
+    assert_eq!(
+        parse("`a-b` = 1; `a-b`"),
+        Ok(Let(
+            "a-b".to_string(),
+            Box::new(Int(1)),
+            Box::new(Var("a-b".to_string()))
+        ))
+    );
+    assert_eq!(parse("`a``b`"), Ok(Var("a`b".to_string())));
+    assert_eq!(parse("````"), Ok(Var("`".to_string())));
+    assert_eq!(parse("``"), Ok(Var("".to_string())));
+    assert!(parse("`a").is_err());
+}
+
+#[test]
+fn test_max_ident_len() {
+    let options = ParseOptions {
+        max_ident_len: Some(5),
+    };
+    assert_eq!(parse_with("Staff", &options), Ok(Var("Staff".to_string())));
+    assert_eq!(
+        parse_with("name <- Staffers", &options),
+        Err("identifier longer than 5 characters: Staffers".to_string())
+    );
+    assert_eq!(
+        parse_with("x = (`first name`); x", &options),
+        Err("identifier longer than 5 characters: first name".to_string())
+    );
+    assert!(parse_with("'a long string' == true", &options).is_ok());
+    assert!(parse_with("row_number() <- x order random()", &options).is_ok());
+    assert!(parse_with("exists (pivot(x, a, b))", &options).is_ok());
+    assert!(parse("Staffers").is_ok());
+}
+
+#[test]
+fn test_select() {
+    assert_eq!(
+        parse("name <- x"),
+        Ok(Select(
+            vec![SelectItem::new("name")],
+            Box::new(Var("x".to_string()))
+        ))
+    );
+    assert_eq!(
+        parse("name as n <- x"),
+        Ok(Select(
+            vec![SelectItem::aliased("name", "n")],
+            Box::new(Var("x".to_string()))
+        ))
+    );
+    assert_eq!(
+        parse("name as n, id <- x"),
+        Ok(Select(
+            vec![SelectItem::aliased("name", "n"), SelectItem::new("id")],
+            Box::new(Var("x".to_string()))
+        ))
+    );
+    assert_eq!(parse("name asn <- x"), Err("expected variable".to_string()));
+    assert_eq!(
+        parse("row_number() as n, row_number() <- x"),
+        Ok(Select(
+            vec![
+                SelectItem {
+                    source: Projection::RowNumber,
+                    alias: Some("n".to_string())
+                },
+                SelectItem {
+                    source: Projection::RowNumber,
+                    alias: None
+                }
+            ],
+            Box::new(Var("x".to_string()))
+        ))
+    );
+    assert_eq!(
+        parse("row_number <- x"),
+        Ok(Select(
+            vec![SelectItem::new("row_number")],
+            Box::new(Var("x".to_string()))
+        ))
+    );
+    assert_eq!(
+        parse("row_number(1) <- x"),
+        Err("expected variable".to_string())
+    );
+}
+
+#[test]
+fn test_order() {
+    assert_eq!(
+        parse("Staff order id"),
+        Ok(Order(
+            Box::new(Var("Staff".to_string())),
+            vec![SortKey::Column("id".to_string())]
+        ))
+    );
+    assert_eq!(
+        parse("Staff order by id, name"),
+        Ok(Order(
+            Box::new(Var("Staff".to_string())),
+            vec![
+                SortKey::Column("id".to_string()),
+                SortKey::Column("name".to_string())
+            ]
+        ))
+    );
+    assert_eq!(
+        parse("Staff order by random(), id"),
+        Ok(Order(
+            Box::new(Var("Staff".to_string())),
+            vec![SortKey::Random, SortKey::Column("id".to_string())]
+        ))
+    );
+    assert_eq!(
+        parse("name <- Staff ? employed order id"),
+        Ok(Select(
+            vec![SelectItem::new("name")],
+            Box::new(Order(
+                Box::new(Where(
+                    Box::new(Var("Staff".to_string())),
+                    Box::new(Var("employed".to_string()))
+                )),
+                vec![SortKey::Column("id".to_string())]
+            ))
+        ))
+    );
+    assert_eq!(
+        parse("Staff order id order name"),
+        parse("(Staff order id) order name")
+    );
+    assert_eq!(parse("ordering"), Ok(Var("ordering".to_string())));
+    assert_eq!(
+        parse("Staff orderly"),
+        Err("unknown function: Staff".to_string())
+    );
+    assert_eq!(
+        parse("Staff order random(1)"),
+        Err("expected variable or random()".to_string())
+    );
+    assert_eq!(parse("( )"), parse("nil"));
+}
+
+#[test]
+fn test_join() {
+    let var = |s: &str| Box::new(Var(s.to_string()));
+
+    assert_eq!(parse("A >< B"), Ok(Join(var("A"), var("B"))));
+    assert_eq!(parse("A><B"), Ok(Join(var("A"), var("B"))));
+    assert_eq!(
+        parse("A >< B >< C"),
+        Ok(Join(Box::new(Join(var("A"), var("B"))), var("C")))
+    );
+    assert_eq!(
+        parse("A * B >< C * D"),
+        Ok(Join(
+            Box::new(Product(var("A"), var("B"))),
+            Box::new(Product(var("C"), var("D")))
+        ))
+    );
+    assert_eq!(
+        parse("A - B >< C - D"),
+        Ok(Difference(
+            Box::new(Difference(var("A"), Box::new(Join(var("B"), var("C"))))),
+            var("D")
+        ))
+    );
+    assert_eq!(
+        parse("A + B >< C"),
+        Ok(Union(var("A"), Box::new(Join(var("B"), var("C")))))
+    );
+}
+
+#[test]
+fn test_rec() {
+    assert_eq!(
+        parse("rec X = a + X; X"),
+        Ok(LetRec(
+            "X".to_string(),
+            Box::new(Union(
+                Box::new(Var("a".to_string())),
+                Box::new(Var("X".to_string()))
+            )),
+            Box::new(Var("X".to_string()))
+        ))
+    );
+    assert_eq!(
+        parse("rec = 1; rec"),
+        Ok(Let(
+            "rec".to_string(),
+            Box::new(Int(1)),
+            Box::new(Var("rec".to_string()))
+        ))
+    );
+    assert!(parse("rec 1 = a; a").is_err());
 }
 
 #[test]
@@ -54,7 +296,7 @@ fn test_program() {
             Box::new(Let(
                 "alice".to_string(),
                 Box::new(Select(
-                    vec!["employed".to_string()],
+                    vec![SelectItem::new("employed")],
                     Box::new(Where(
                         Box::new(Var("Staff".to_string())),
                         Box::new(Equals(
@@ -66,7 +308,7 @@ fn test_program() {
                 Box::new(Let(
                     "bob".to_string(),
                     Box::new(Select(
-                        vec!["employed".to_string()],
+                        vec![SelectItem::new("employed")],
                         Box::new(Where(
                             Box::new(Var("Staff".to_string())),
                             Box::new(Equals(
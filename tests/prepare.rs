@@ -0,0 +1,93 @@
+use sdb::{parse_schemas, prepare, read_eval, Env, Exp::*, Type};
+
+#[test]
+fn test_prepare() {
+    let schemas = parse_schemas("Staff = id, name, employed : int, str, bool; nil").unwrap();
+    let query = prepare("id as staff_id, name <- Staff ? employed", &schemas).unwrap();
+    assert_eq!(
+        query.schema(),
+        &vec![
+            ("staff_id".to_string(), Type::Int),
+            ("name".to_string(), Type::Str)
+        ]
+    );
+
+    let (staff, _) = read_eval(
+        "id, name, employed : 1, 'Alice', true, 2, 'Bob', false",
+        &Env::new(),
+    )
+    .unwrap();
+    let env = Env::from([("Staff".to_string(), staff)]);
+    assert_eq!(
+        query.execute(&env),
+        Ok(Table(
+            vec!["staff_id".to_string(), "name".to_string()],
+            vec![Int(1), Str("Alice".to_string())]
+        ))
+    );
+
+    let (bad, _) = read_eval("id, name, employed : 'one', 'Alice', true", &Env::new()).unwrap();
+    assert_eq!(
+        query.execute(&Env::from([("Staff".to_string(), bad)])),
+        Err("Staff: row 1: expected int in column `id`, found 'one'".to_string())
+    );
+}
+
+#[test]
+fn test_infer() {
+    let schemas =
+        parse_schemas("Staff = id, name : int, str; Sizes = id, size : int, int; nil").unwrap();
+    let schema = |src| prepare(src, &schemas).map(|query| query.schema().clone());
+
+    assert_eq!(
+        schema("Staff >< Sizes order size"),
+        Ok(vec![
+            ("id".to_string(), Type::Int),
+            ("name".to_string(), Type::Str),
+            ("size".to_string(), Type::Int)
+        ])
+    );
+    assert_eq!(
+        schema("Staff + id, name : null, 'Bob'"),
+        Ok(vec![
+            ("id".to_string(), Type::Int),
+            ("name".to_string(), Type::Str)
+        ])
+    );
+    assert_eq!(
+        schema("x : (exists(Staff ? id == 1))"),
+        Ok(vec![("x".to_string(), Type::Bool)])
+    );
+    assert_eq!(
+        schema("Staff ? name == 1"),
+        Err("cannot compare str with int".to_string())
+    );
+    assert_eq!(
+        schema("size <- Staff"),
+        Err("no column `size` to select".to_string())
+    );
+    assert_eq!(
+        schema("Staff + Sizes"),
+        Err("expected columns id, name, found id, size".to_string())
+    );
+    assert_eq!(
+        schema("Staff = id, name : 'one', 'Alice'; Staff"),
+        Err("Staff: expected int in column `id`, found str".to_string())
+    );
+    assert_eq!(
+        schema("T = a : 1; T = b : 'x'; T"),
+        Ok(vec![("b".to_string(), Type::Str)])
+    );
+    assert_eq!(
+        schema("rec T = (a : 1) + T; T = b : 'x'; T"),
+        Ok(vec![("b".to_string(), Type::Str)])
+    );
+    assert_eq!(
+        schema("x : null"),
+        Err("cannot infer type of column `x`".to_string())
+    );
+    assert_eq!(
+        schema("Salaries"),
+        Err("unknown variable: Salaries".to_string())
+    );
+}
@@ -0,0 +1,45 @@
+#![cfg(feature = "serde")]
+
+use sdb::{parse, Exp};
+
+macro_rules! run {
+    ($input:expr) => {{
+        let exp = parse($input).unwrap();
+        let json = serde_json::to_string(&exp).unwrap();
+        let round_tripped: Exp = serde_json::from_str(&json).unwrap();
+        assert_eq!(round_tripped, exp, "{}", $input);
+    }};
+}
+
+#[test]
+fn test_json_round_trip() {
+    // The program from `test_program` in tests/parse.rs.
+    run!(
+        r#"
+/* welcome to
+my program */
+
+Staff =
+  name, id, employed:
+  'Alice', 1, true,
+  'Bob', 2, false;
+
+alice_or_bob_employed = (
+  alice = employed <- Staff ? name == 'Alice';
+  bob = employed <- Staff ? name == 'Bob';
+  alice || bob
+);
+
+alice_or_bob_employed
+"#
+    );
+
+    run!("employed as e, row_number() as n, row_number() <- Staff");
+    run!("Staff order name, random()");
+    run!("Staff >< Roles");
+    run!("Staff * Staff - Staff + nil");
+    run!("pivot(Staff, name, id)");
+    run!("Staff ? not (exists (Staff ? id == -1)) && employed");
+    run!("rec Reach = Edges + (from, to <- Reach); Reach");
+    run!("a, b : null, 'x'");
+}
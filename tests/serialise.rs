@@ -29,14 +29,88 @@ fn test_serialise() {
     );
     run!("a = (b = c; d); e", "a = (b = c; d); e");
 
+    run!("a >< b * c", "a >< b * c");
+    run!("(a >< b) * c", "(a >< b) * c");
+    run!("a - b >< c", "a - b >< c");
+    run!("(a - b) >< c", "(a - b) >< c");
+    run!("a >< (b >< c)", "a >< (b >< c)");
+
     run!("a * b * c", "a * b * c");
     run!("(a * b) * c", "a * b * c");
     run!("a * (b * c)", "a * (b * c)");
 
+    run!("null", "null");
+    run!("a, b : null, nullable", "a, b : null, nullable");
+
     run!("nil", "nil");
     run!("nil : nil", "nil");
     run!("nil <- a : 1", "nil <- a : 1");
 
+    run!("a as b, c <- d", "a as b, c <- d");
+    run!("a as b,c<-d", "a as b, c <- d");
+
+    run!("pivot(a, b, c)", "pivot (a, b, c)");
+    run!("pivot((t ? x), c, d)", "pivot ((t ? x), c, d)");
+
+    run!("a <- b order c, d", "a <- b order c, d");
+    run!("a ? b order by c", "a ? b order c");
+    run!("(a order b) ? c", "(a order b) ? c");
+
+    run!("`first name`: 'Alice'", "`first name` : 'Alice'");
+    run!("`x` = `order`; `by` <- x", "x = `order`; `by` <- x");
+    run!("a order `by`", "a order `by`");
+    run!("`a``b` = 1; `a``b`", "`a``b` = 1; `a``b`");
+    run!("`` : 1", "`` : 1");
+    run!("a, b : 'it''s', ''''", "a, b : 'it''s', ''''");
+    run!("a order by random( ), b", "a order random (), b");
+    run!("row_number() as n, a <- b", "row_number () as n, a <- b");
+    run!("trueish, nils : 1, 2", "trueish, nils : 1, 2");
+
     run!("a : b || c, d || e", "a : b || c, d || e");
     run!("a : (b : c), (d : e)", "a : (b : c), (d : e)");
 }
+
+#[test]
+fn test_not() {
+    run!("not (not true)", "not (not true)");
+    run!("not (a || b)", "not (a || b)");
+    run!("not a && b", "not a && b");
+    run!("not (-1)", "not (-1)");
+    run!("not -1", "not - 1");
+}
+
+#[test]
+fn test_exists() {
+    run!("exists (T ? a)", "exists (T ? a)");
+    run!("not (exists T)", "not (exists T)");
+    run!("exists (-1)", "exists (-1)");
+}
+
+#[test]
+fn test_rec() {
+    run!("rec X = a + (b <- X); X", "rec X = a + (b <- X); X");
+    run!("y = (rec X = a + X; X); y", "y = (rec X = a + X; X); y");
+}
+
+#[test]
+fn test_display() {
+    let program = r#"
+Staff =
+  name, id, employed:
+  'Alice', 1, true,
+  'Bob', 2, false;
+
+Pairs = (name <- Staff) * (id <- Staff);
+
+alice_or_bob = (
+  alice = employed <- Staff ? name == 'Alice';
+  bob = employed <- Staff ? name == 'Bob' && not (id == 1 || employed);
+  alice || bob
+);
+
+a, b : (x : 1), Pairs - (Staff - Staff)
+"#;
+    let parsed = parse(program).unwrap();
+    assert_eq!(parse(&parsed.to_string()), Ok(parsed.clone()));
+    assert_eq!(parsed.to_string(), serialise(parsed));
+}